@@ -1,11 +1,108 @@
 pub(crate) mod application {
-    use std::{collections::HashMap, sync::Arc};
+    use std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        sync::Arc,
+    };
 
     use tokio::sync::{Mutex, MutexGuard};
 
     use crate::config;
 
-    type Inner = Arc<Mutex<HashMap<u32, config::Rect>>>;
+    type Inner = Arc<Mutex<Placements>>;
+
+    #[derive(Debug, Default, Clone)]
+    pub struct Placements {
+        rects: HashMap<u32, config::Rect>,
+        borders: HashMap<u32, config::Border>,
+        /// Colour drawn over the whole rect of applications that are hung or restarting
+        placeholders: HashMap<u32, [f32; 4]>,
+        /// Applications whose windows should be asked to close
+        close_requests: Vec<u32>,
+        /// Applications that have a rect but aren't drawn and don't get input, such as the new applications
+        /// of a layout that is being switched to
+        hidden: HashSet<u32>,
+        /// Images drawn by the compositor in place of an application window
+        image_tiles: HashMap<u32, config::ImageTile>,
+        /// Widgets drawn on top of everything, in the order they are drawn
+        overlay: Vec<config::OverlayWidget>,
+        background: config::Background,
+        /// Mirrors by id. Ones with a higher id are drawn over ones with a lower id.
+        mirrors: BTreeMap<u32, config::Mirror>,
+    }
+
+    impl Placements {
+        /// Area left for the application itself once the compositor drawn border is removed
+        pub fn content_rect(&self, pid: u32) -> Option<config::Rect> {
+            let rect = self.rects.get(&pid)?;
+            Some(match self.borders.get(&pid) {
+                Some(border) => rect.inset(border.width),
+                None => *rect,
+            })
+        }
+
+        /// [Placements::content_rect] of applications that aren't hidden
+        pub fn visible_content_rect(&self, pid: u32) -> Option<config::Rect> {
+            if self.hidden.contains(&pid) {
+                return None;
+            }
+            self.content_rect(pid)
+        }
+
+        pub fn border(&self, pid: u32) -> Option<config::Border> {
+            self.borders.get(&pid).copied()
+        }
+
+        /// Rects that should be covered by a placeholder, along with the placeholder's colour
+        pub fn placeholders(&self) -> Vec<(u32, config::Rect, [f32; 4])> {
+            self.placeholders
+                .iter()
+                .filter(|(pid, _)| !self.hidden.contains(pid))
+                .filter_map(|(pid, color)| Some((*pid, *self.rects.get(pid)?, *color)))
+                .collect()
+        }
+
+        /// Image tiles that aren't hidden, along with the area left for the images inside their border
+        pub fn image_tiles(&self) -> Vec<(u32, config::Rect, &config::ImageTile)> {
+            self.image_tiles
+                .iter()
+                .filter_map(|(pid, tile)| Some((*pid, self.visible_content_rect(*pid)?, tile)))
+                .collect()
+        }
+
+        pub fn overlay(&self) -> &[config::OverlayWidget] {
+            &self.overlay
+        }
+
+        pub fn background(&self) -> &config::Background {
+            &self.background
+        }
+
+        /// Mirror `id` along with the area its source is drawn in, if the source is showing
+        pub fn mirror(&self, id: u32) -> Option<(config::Mirror, config::Rect)> {
+            let mirror = self.mirrors.get(&id)?;
+            Some((*mirror, self.visible_content_rect(mirror.source_pid)?))
+        }
+
+        /// [Placements::mirror] for every mirror whose source is showing, front to back
+        pub fn mirrors(&self) -> Vec<(u32, config::Mirror, config::Rect)> {
+            self.mirrors
+                .keys()
+                .rev()
+                .filter_map(|id| {
+                    let (mirror, source_area) = self.mirror(*id)?;
+                    Some((*id, mirror, source_area))
+                })
+                .collect()
+        }
+
+        fn remove(&mut self, pid: u32) -> bool {
+            self.borders.remove(&pid);
+            self.placeholders.remove(&pid);
+            self.hidden.remove(&pid);
+            self.image_tiles.remove(&pid);
+            self.rects.remove(&pid).is_some()
+        }
+    }
 
     /// Handle that can be used to query the position of an application. Used
     /// internally by the compositor.
@@ -13,12 +110,23 @@ pub(crate) mod application {
     pub struct CompositorApplicationViewer(Inner);
 
     impl CompositorApplicationViewer {
-        pub async fn application_rect_by_pid(&self, pid: u32) -> Option<config::Rect> {
-            self.0.lock().await.get(&pid).copied()
+        /// Area left for the application itself once the compositor drawn border is removed
+        pub fn application_content_rect_by_pid_blocking(&self, pid: u32) -> Option<config::Rect> {
+            self.0.blocking_lock().content_rect(pid)
+        }
+
+        /// A copy of all placements. Taken once per frame so changes made together show up in the same frame.
+        pub async fn snapshot(&self) -> Placements {
+            let placements = self.0.lock().await;
+            Placements {
+                close_requests: Vec::new(),
+                ..placements.clone()
+            }
         }
 
-        pub fn application_rect_by_pid_blocking(&self, pid: u32) -> Option<config::Rect> {
-            self.0.blocking_lock().get(&pid).copied()
+        /// Applications whose windows should be sent `xdg_toplevel.close` since the last call
+        pub async fn take_close_requests(&self) -> Vec<u32> {
+            std::mem::take(&mut self.0.lock().await.close_requests)
         }
     }
 
@@ -39,7 +147,7 @@ pub(crate) mod application {
 
         /// Set where the compositor should position an application on the global rendering canvas
         pub async fn set_application_position(&self, pid: u32, rect: config::Rect) {
-            self.0.lock().await.insert(pid, rect);
+            self.0.lock().await.rects.insert(pid, rect);
         }
 
         /// Set where the compositor should position an application on the global rendering canvas
@@ -47,21 +155,137 @@ pub(crate) mod application {
             PositionSetterGuard(self.0.lock().await)
         }
 
+        /// Ask the windows of an application to close. They are sent the request on the next frame.
+        pub async fn request_close(&self, pid: u32) {
+            self.0.lock().await.close_requests.push(pid);
+        }
+
+        /// Cover an application with a placeholder, or stop covering it
+        pub async fn set_application_placeholder(&self, pid: u32, placeholder: Option<[f32; 4]>) {
+            let mut placements = self.0.lock().await;
+            match placeholder {
+                Some(color) => placements.placeholders.insert(pid, color),
+                None => placements.placeholders.remove(&pid),
+            };
+        }
+
+        /// Same as [CompositorApplicationHandle::set_application_placeholder] with `None`, for use outside
+        /// of async code
+        pub fn remove_application_placeholder_blocking(&self, pid: u32) {
+            self.0.blocking_lock().placeholders.remove(&pid);
+        }
+
+        /// Add an overlay widget on top of the others, or replace the one with the same id in place
+        pub async fn set_overlay(&self, widget: config::OverlayWidget) {
+            let overlay = &mut self.0.lock().await.overlay;
+            match overlay.iter_mut().find(|existing| existing.id == widget.id) {
+                Some(existing) => *existing = widget,
+                None => overlay.push(widget),
+            }
+        }
+
+        /// Remove the overlay widget with `id`, or all of them. Returns whether anything was removed.
+        pub async fn clear_overlay(&self, id: Option<&str>) -> bool {
+            let overlay = &mut self.0.lock().await.overlay;
+            let before = overlay.len();
+            overlay.retain(|widget| id.is_some_and(|id| widget.id != id));
+            overlay.len() != before
+        }
+
+        pub async fn set_background(&self, background: config::Background) {
+            self.0.lock().await.background = background;
+        }
+
+        pub async fn add_mirror(&self, id: u32, mirror: config::Mirror) {
+            self.0.lock().await.mirrors.insert(id, mirror);
+        }
+
+        /// Returns whether there was a mirror with `id`
+        pub async fn remove_mirror(&self, id: u32) -> bool {
+            self.0.lock().await.mirrors.remove(&id).is_some()
+        }
+
+        /// Remove every mirror of `source_pid`
+        pub async fn remove_mirrors_of(&self, source_pid: u32) {
+            self.0
+                .lock()
+                .await
+                .mirrors
+                .retain(|_, mirror| mirror.source_pid != source_pid);
+        }
+
+        /// Have the mirrors of `from` show `to` instead, for when an application is restarted
+        pub async fn retarget_mirrors(&self, from: u32, to: u32) {
+            for mirror in self.0.lock().await.mirrors.values_mut() {
+                if mirror.source_pid == from {
+                    mirror.source_pid = to;
+                }
+            }
+        }
+
         /// Stop rendering an application on the global canvas
         pub async fn remove_application_position(&self, pid: u32) -> bool {
-            self.0.lock().await.remove(&pid).is_some()
+            self.0.lock().await.remove(pid)
+        }
+
+        /// Switch the canvas over to a new layout. `placed` applications are moved into their rects and shown,
+        /// `removed` ones stop being rendered. Everything changes at once so it shows up in a single frame.
+        pub async fn switch_layout(
+            &self,
+            placed: &[(u32, config::Rect, Option<config::Border>)],
+            removed: &[u32],
+        ) {
+            let mut placements = self.0.lock().await;
+            for pid in removed {
+                placements.remove(*pid);
+            }
+            for (pid, rect, border) in placed {
+                placements.rects.insert(*pid, *rect);
+                match border {
+                    Some(border) => placements.borders.insert(*pid, *border),
+                    None => placements.borders.remove(pid),
+                };
+                placements.hidden.remove(pid);
+            }
         }
     }
 
     /// Locks all the viewers temporarily while the position is being set. This is necessary since
     /// to get the pid of a process you need to spawn the process, which will try to view the position.
     #[derive(Debug)]
-    pub struct PositionSetterGuard<'a>(MutexGuard<'a, HashMap<u32, config::Rect>>);
+    pub struct PositionSetterGuard<'a>(MutexGuard<'a, Placements>);
 
     impl<'a> PositionSetterGuard<'a> {
         /// Set where the compositor should position an application on the global rendering canvas
         pub fn set_application_position(&mut self, pid: u32, rect: config::Rect) {
-            self.0.insert(pid, rect);
+            self.0.rects.insert(pid, rect);
+        }
+
+        /// Set (or clear) the border the compositor draws around an application
+        pub fn set_application_border(&mut self, pid: u32, border: Option<config::Border>) {
+            match border {
+                Some(border) => self.0.borders.insert(pid, border),
+                None => self.0.borders.remove(&pid),
+            };
+        }
+
+        /// Keep an application from being drawn or getting input until it's shown by
+        /// [CompositorApplicationHandle::switch_layout]
+        pub fn hide_application(&mut self, pid: u32) {
+            self.0.hidden.insert(pid);
+        }
+
+        /// Have the compositor draw `tile` in the rect of `pid` instead of an application window
+        pub fn set_image_tile(&mut self, pid: u32, tile: config::ImageTile) {
+            self.0.image_tiles.insert(pid, tile);
+        }
+
+        /// Set (or clear) the placeholder drawn over an application
+        pub fn set_application_placeholder(&mut self, pid: u32, placeholder: Option<[f32; 4]>) {
+            match placeholder {
+                Some(color) => self.0.placeholders.insert(pid, color),
+                None => self.0.placeholders.remove(&pid),
+            };
         }
     }
 }
@@ -1,21 +1,786 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::process::Child;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CompositorConfig {
     pub width: u32,
     pub height: u32,
+    /// The physical screens that make up the canvas, used to work out [AppConfig::slot]. Defaults to a
+    /// single screen covering the whole canvas.
+    #[serde(default)]
+    pub screens: Vec<Rect>,
     pub launch: Vec<AppConfig>,
+    /// A cgroup v2 directory delegated to the compositor (e.g. with systemd's `Delegate=yes`). Each app gets
+    /// its own cgroup inside of it to enforce [AppConfig::limits].
+    #[serde(default)]
+    pub cgroup_root: Option<PathBuf>,
+    /// What the control socket is allowed to spawn. Apps from [CompositorConfig::launch] are trusted.
+    #[serde(default)]
+    pub spawn_policy: SpawnPolicy,
+    /// Who may connect to the control socket and what they may do
+    #[serde(default)]
+    pub controller_access: ControllerAccess,
+    /// How applications are checked for being hung
+    #[serde(default)]
+    pub ping: PingConfig,
+    /// How long applications get to exit when the compositor shuts down or they are killed gracefully
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// Switch to an attract layout when nobody has touched the wall for a while
+    #[serde(default)]
+    pub idle: Option<IdleConfig>,
+    /// Named sets of applications that together make up everything shown on the wall
+    #[serde(default)]
+    pub layouts: BTreeMap<String, Vec<AppConfig>>,
+    /// Which of the [CompositorConfig::layouts] is shown at what time. The first entry that is active wins.
+    /// A scheduled layout replaces everything that is running, including [CompositorConfig::launch].
+    /// Outside of every entry whatever was last shown stays up.
+    #[serde(default)]
+    pub schedule: Vec<ScheduleEntry>,
+    /// Widgets drawn by the compositor on top of every application
+    #[serde(default)]
+    pub overlay: Vec<OverlayWidget>,
+    /// Which clients may anchor themselves to the edges of the canvas or a screen with `zwlr_layer_shell_v1`
+    #[serde(default)]
+    pub layer_shell: LayerShellConfig,
+    /// What's drawn behind everything else. Can be changed with [AppControllerCommand::SetBackground].
+    #[serde(default)]
+    pub background: Background,
+    /// Applications shown a second time in another rect, such as the same map on every screen. More can be
+    /// added with [AppControllerCommand::Mirror].
+    #[serde(default)]
+    pub mirrors: Vec<MirrorConfig>,
+}
+
+impl CompositorConfig {
+    /// [CompositorConfig::screens], or a single screen covering the canvas if there aren't any
+    pub fn screen_rects(&self) -> Vec<Rect> {
+        match self.screens.is_empty() {
+            true => vec![Rect {
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+            }],
+            false => self.screens.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Background {
+    Color {
+        /// RGBA colour with each channel between 0 and 1
+        color: [f32; 4],
+    },
+    /// One image across the whole canvas
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        fit: FitMode,
+        /// Shown where the image doesn't reach, and while it's loading
+        #[serde(default = "default_background_color")]
+        color: [f32; 4],
+    },
+    /// A separate image for each of the [CompositorConfig::screens], in the same order
+    Screens {
+        paths: Vec<PathBuf>,
+        #[serde(default)]
+        fit: FitMode,
+        #[serde(default = "default_background_color")]
+        color: [f32; 4],
+    },
+}
+
+impl Background {
+    /// The colour the canvas is cleared to before anything is drawn on it
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            Background::Color { color }
+            | Background::Image { color, .. }
+            | Background::Screens { color, .. } => *color,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color {
+            color: default_background_color(),
+        }
+    }
+}
+
+fn default_background_color() -> [f32; 4] {
+    [0.67843137254, 0.141176, 0.2235294, 1.0]
+}
+
+/// Mirrors every application started with the [AppConfig::name] `source`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MirrorConfig {
+    pub source: String,
+    pub rect: Rect,
+    #[serde(default)]
+    pub input: MirrorInput,
+}
+
+/// The windows of an application drawn again in `rect`, scaled to fill it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Mirror {
+    pub source_pid: u32,
+    pub rect: Rect,
+    pub input: MirrorInput,
+}
+
+/// What happens to input on a mirror
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MirrorInput {
+    /// The mirror can only be looked at
+    #[default]
+    Disabled,
+    /// Passed on to the mirrored application as if it happened in the same spot on the application itself
+    Forward,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LayerShellConfig {
+    /// [AppConfig::name]s, or [AppConfig::executable]s of unnamed applications, that may create layer
+    /// surfaces. Layer surfaces from anything else are closed straight away. Clients are matched by the pid
+    /// they connect with, so this only works for applications that connect themselves. A wrapper script
+    /// that starts the real client as a child process isn't recognized and its layer surfaces are closed.
+    pub allowed: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScheduleEntry {
+    /// Name of one of the [CompositorConfig::layouts]
+    pub layout: String,
+    /// Days the entry starts on. Every day if empty.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Local time the layout is shown from
+    pub start: TimeOfDay,
+    /// Local time the layout stops being shown. If this is before [ScheduleEntry::start] the entry runs
+    /// past midnight into the next day. The same as `start` means all day.
+    pub end: TimeOfDay,
+}
+
+impl ScheduleEntry {
+    /// Whether the entry covers `time` on `day`
+    pub fn is_active(&self, day: Weekday, time: TimeOfDay) -> bool {
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if self.start == self.end {
+            on(day)
+        } else if self.start < self.end {
+            on(day) && self.start <= time && time < self.end
+        } else {
+            (on(day) && self.start <= time) || (on(day.previous()) && time < self.end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Weekday {
+    Sunday,
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+}
+
+impl Weekday {
+    /// `days_from_sunday` is taken modulo 7, so 0 is Sunday, 1 is Monday and so on
+    pub fn from_days_from_sunday(days_from_sunday: u32) -> Self {
+        match days_from_sunday % 7 {
+            0 => Weekday::Sunday,
+            1 => Weekday::Monday,
+            2 => Weekday::Tuesday,
+            3 => Weekday::Wednesday,
+            4 => Weekday::Thursday,
+            5 => Weekday::Friday,
+            _ => Weekday::Saturday,
+        }
+    }
+
+    pub fn previous(self) -> Self {
+        Self::from_days_from_sunday(self as u32 + 6)
+    }
+}
+
+/// A time of day written as `HH:MM`, stored as minutes since midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(pub u32);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("'{value}' is not a time of day like '07:30'");
+        let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+        let hours: u32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay(hours * 60 + minutes))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        format!("{:02}:{:02}", time.0 / 60, time.0 % 60)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdleConfig {
+    /// Time without any navigation events before the attract layout is shown
+    pub timeout_ms: u64,
+    /// Applications shown while idle (e.g. `apps/idle`). Everything that was running is stopped and comes
+    /// back on the next touch.
+    pub attract: Vec<AppConfig>,
+}
+
+/// Applications are stopped by asking their windows to close, then sending SIGTERM and finally SIGKILL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Time between `xdg_toplevel.close` and SIGTERM
+    pub close_grace_ms: u64,
+    /// Time between SIGTERM and SIGKILL
+    pub term_grace_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            close_grace_ms: 3000,
+            term_grace_ms: 5000,
+        }
+    }
+}
+
+/// Signals that can be sent with [AppControllerCommand::Kill]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KillSignal {
+    Term,
+    Int,
+    Hup,
+    Quit,
+    Usr1,
+    Usr2,
+    Kill,
+}
+
+/// Applications are sent an `xdg_wm_base.ping` every [PingConfig::interval_ms] and are considered not
+/// responding if they don't answer within [PingConfig::timeout_ms].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PingConfig {
+    /// Time between pings. Pinging is turned off when this is 0.
+    pub interval_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 5000,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Controller clients are identified by the uid and gids of the connecting process. Root and the user running
+/// the compositor are always [ControllerRole::Admin].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ControllerAccess {
+    pub admin: Principals,
+    pub operator: Principals,
+    pub read_only: Principals,
+    /// Role of clients that aren't listed anywhere. They are disconnected if not set.
+    pub default_role: Option<ControllerRole>,
+    /// File permissions of the control socket. Connecting needs write permission.
+    pub socket_mode: u32,
+}
+
+impl Default for ControllerAccess {
+    fn default() -> Self {
+        Self {
+            admin: Default::default(),
+            operator: Default::default(),
+            read_only: Default::default(),
+            default_role: None,
+            socket_mode: 0o660,
+        }
+    }
+}
+
+impl ControllerAccess {
+    /// The highest role any of the ids were given
+    pub fn role_of(&self, uid: u32, gids: &[u32]) -> Option<ControllerRole> {
+        [
+            (&self.admin, ControllerRole::Admin),
+            (&self.operator, ControllerRole::Operator),
+            (&self.read_only, ControllerRole::ReadOnly),
+        ]
+        .into_iter()
+        .find(|(principals, _)| principals.contains(uid, gids))
+        .map(|(_, role)| role)
+        .or(self.default_role)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Principals {
+    pub uids: Vec<u32>,
+    pub gids: Vec<u32>,
+}
+
+impl Principals {
+    pub fn contains(&self, uid: u32, gids: &[u32]) -> bool {
+        self.uids.contains(&uid) || gids.iter().any(|gid| self.gids.contains(gid))
+    }
+}
+
+/// What a controller client is allowed to do. Each role can do everything the roles before it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ControllerRole {
+    /// Look at what's running
+    ReadOnly,
+    /// Move apps around, switch layouts and read their logs
+    Operator,
+    /// Start and stop apps
+    Admin,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpawnPolicy {
+    /// Executables [AppControllerCommand::Spawn] may run, matched exactly as written. Anything can be run if
+    /// not set. When set `Spawn` can only set `name`, `executable`, `args`, `area`, `slot`, `border`,
+    /// `restart` and `unresponsive`. Everything else needs a named app from [SpawnPolicy::apps].
+    pub allowed_executables: Option<Vec<String>>,
+    /// Trusted app definitions that can be started by name with [AppControllerCommand::SpawnApp]
+    pub apps: BTreeMap<String, AppConfig>,
+    /// Only allow [AppControllerCommand::SpawnApp]
+    pub named_only: bool,
+    /// Every spawn attempt through the control socket is appended to this file as a line of JSON
+    pub audit_log: Option<PathBuf>,
+}
+
+// Deserialized through the impl below, which checks that the application has somewhere to go
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(remote = "Self")]
 pub struct AppConfig {
+    /// Used to refer to the application from [AppConfig::depends_on]
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Not needed for [AppConfig::image] tiles
+    #[serde(default)]
     pub executable: String,
+    /// `{x}`, `{y}`, `{width}`, `{height}`, `{screen_width}`, `{screen_height}` and `{app_id}` are replaced
+    /// when the application is started. The same goes for the values of [AppConfig::env].
     pub args: Vec<String>,
+    /// Required unless [AppConfig::slot] is set, in which case it's ignored
+    #[serde(default)]
+    pub area: Rect,
+    /// Place the application on the wall by screen instead of by pixels. The [AppConfig::area] is worked out
+    /// when it's started.
+    #[serde(default)]
+    pub slot: Option<Slot>,
+    /// Extra environment variables. `WAYLAND_DISPLAY`, `XDG_RUNTIME_DIR`, `SWALL_X`, `SWALL_Y`,
+    /// `SWALL_WIDTH`, `SWALL_HEIGHT` and `SWALL_CONTROL_SOCKET` are always set by the compositor and can't
+    /// be overridden.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Start with an empty environment instead of inheriting the compositor's
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Working directory. Inherited from the compositor if not set.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// User name or uid to run the application as. The compositor needs to be privileged to do this.
+    /// Supplementary groups are dropped.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Group name or gid to run the application as. Defaults to the primary group of
+    /// [AppConfig::user].
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Border drawn by the compositor around the inside edge of [AppConfig::area]. The application is
+    /// shrunk to fit inside of it.
+    #[serde(default)]
+    pub border: Option<Border>,
+    /// What to do when the application exits without being killed by the app controller
+    #[serde(default)]
+    pub restart: RestartConfig,
+    /// Where the application's stdout and stderr end up
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Only applies to [CompositorConfig::launch]. Names of applications that have to be ready before
+    /// this one is started.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Only applies to [CompositorConfig::launch]. Wait this long after the dependencies are ready before
+    /// starting.
+    #[serde(default)]
+    pub start_delay_ms: u64,
+    /// Only applies to [CompositorConfig::launch]. When the application counts as ready for the
+    /// applications depending on it.
+    #[serde(default)]
+    pub ready: ReadinessCheck,
+    /// Give up on the application becoming ready after this long
+    #[serde(default = "default_ready_timeout_ms")]
+    pub ready_timeout_ms: u64,
+    /// What to do when the application stops answering pings
+    #[serde(default)]
+    pub unresponsive: UnresponsivePolicy,
+    /// Show images in the area instead of starting [AppConfig::executable]. The compositor draws them
+    /// itself so no process is started. Only the placement options apply.
+    #[serde(default)]
+    pub image: Option<ImageTile>,
+}
+
+impl<'de> Deserialize<'de> for AppConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let config = AppConfig::deserialize(deserializer)?;
+        if config.slot.is_none() && (config.area.width == 0 || config.area.height == 0) {
+            return Err(serde::de::Error::custom(
+                "an application needs an area or a slot",
+            ));
+        }
+        if let Some(Slot::Grid { column_span: 0, .. } | Slot::Grid { row_span: 0, .. }) =
+            config.slot
+        {
+            return Err(serde::de::Error::custom(
+                "a grid slot has to span at least one column and row",
+            ));
+        }
+        Ok(config)
+    }
+}
+
+impl Serialize for AppConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        AppConfig::serialize(self, serializer)
+    }
+}
+
+/// A still image, or a slideshow when there is more than one
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ImageTile {
+    /// Shown in order, starting over after the last one
+    pub images: Vec<SlideshowImage>,
+    #[serde(default)]
+    pub fit: FitMode,
+    /// How long each image is shown unless it sets its own [SlideshowImage::duration_ms]
+    #[serde(default = "default_image_duration_ms")]
+    pub duration_ms: u64,
+    /// How long each image takes to fade in over the one before it. Zero switches straight away.
+    #[serde(default)]
+    pub crossfade_ms: u64,
+}
+
+fn default_image_duration_ms() -> u64 {
+    10_000
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SlideshowImage {
+    /// PNG or JPEG file
+    pub path: PathBuf,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+}
+
+/// How an image is fitted into an area with a different aspect ratio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitMode {
+    /// Scale to fit inside the area, leaving the rest of it empty
+    #[default]
+    Contain,
+    /// Scale to cover the whole area, cutting off what doesn't fit
+    Cover,
+    /// Stretch to the size of the area, ignoring the aspect ratio
+    Stretch,
+}
+
+fn default_ready_timeout_ms() -> u64 {
+    30_000
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ReadinessCheck {
+    /// Ready as soon as the process is running
+    #[default]
+    Spawned,
+    /// Ready once the application has opened a window
+    Window,
+    /// Ready once a file exists, such as a socket the application creates
+    Path { path: PathBuf },
+    /// Ready once something accepts TCP connections on the address (e.g. `127.0.0.1:8080`)
+    Tcp { address: String },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UnresponsivePolicy {
+    /// Kill the application and start it again straight away
+    pub restart: bool,
+    /// RGBA colour drawn over the application while it is not responding. Also covers a restarted
+    /// application until it opens its window again.
+    pub placeholder: Option<[f32; 4]>,
+}
+
+/// Limits on how much of the machine an application can use. Apart from [ResourceLimits::nice] these are
+/// only fully enforced when [CompositorConfig::cgroup_root] is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Bytes of memory. Without a cgroup this limits the address space (`RLIMIT_AS`) instead, which is
+    /// much larger than the memory actually used by most apps.
+    pub memory_max: Option<u64>,
+    /// Share of the CPU relative to other apps, between 1 and 10000 (default 100). Needs a cgroup.
+    pub cpu_weight: Option<u64>,
+    /// Number of processes and threads. Needs a cgroup.
+    pub pids_max: Option<u64>,
+    /// Scheduling priority between -20 (highest) and 19 (lowest). It's set after switching to
+    /// [AppConfig::user], so with a user set it can't go below the compositor's own nice unless that user
+    /// is allowed to raise its priority. The application fails to start otherwise.
+    pub nice: Option<i32>,
+}
+
+/// Resources currently used by an application. Fields that couldn't be read are left empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResourceUsage {
+    pub memory_bytes: Option<u64>,
+    /// Total CPU time used in microseconds
+    pub cpu_usage_usec: Option<u64>,
+    pub pids: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Number of lines kept in memory for [AppControllerCommand::Logs]
+    pub buffer_lines: usize,
+    /// Also append all output to this file
+    pub file: Option<PathBuf>,
+    /// Size after which the log file is rotated
+    pub max_file_bytes: u64,
+    /// Number of rotated files (`<file>.1`, `<file>.2`, ...) kept around
+    pub max_files: u32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            buffer_lines: 1000,
+            file: None,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// Only restart if the application exited with a non-zero code or was killed by a signal
+    OnFailure,
+    Always,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RestartConfig {
+    pub policy: RestartPolicy,
+    /// Give up after this many restarts in a row. Retries forever if not set.
+    pub max_retries: Option<u32>,
+    /// Delay before the first restart. Doubles with every restart in a row.
+    pub initial_backoff_ms: u64,
+    /// Upper bound of the delay between restarts. An application that stays up longer than this is
+    /// considered healthy again and goes back to [RestartConfig::initial_backoff_ms].
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            policy: RestartPolicy::Never,
+            max_retries: Some(5),
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 60_000,
+        }
+    }
+}
+
+impl RestartConfig {
+    /// How long to wait before restarting when the application has already been restarted `consecutive`
+    /// times in a row
+    pub fn backoff(&self, consecutive: u32) -> Duration {
+        let backoff = self
+            .initial_backoff_ms
+            .saturating_mul(1u64.checked_shl(consecutive).unwrap_or(u64::MAX));
+        Duration::from_millis(backoff.min(self.max_backoff_ms))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Border {
+    pub width: u32,
+    /// RGBA colour with each channel between 0 and 1
+    pub color: [f32; 4],
+}
+
+/// Something the compositor draws itself on top of the applications, without a process behind it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OverlayWidget {
+    /// Used to replace or remove the widget with [AppControllerCommand::SetOverlay] and
+    /// [AppControllerCommand::ClearOverlay]
+    pub id: String,
     pub area: Rect,
+    #[serde(flatten)]
+    pub kind: WidgetKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum WidgetKind {
+    /// Fixed text. With a `background` this makes a banner.
+    Text {
+        text: String,
+        #[serde(default)]
+        style: TextStyle,
+        /// RGBA colour filling the whole area behind the text. Transparent if not set.
+        #[serde(default)]
+        background: Option<[f32; 4]>,
+    },
+    /// The local time, formatted like `strftime` (e.g. `%H:%M` or `%A %e %B`)
+    Clock {
+        #[serde(default = "default_clock_format")]
+        format: String,
+        #[serde(default)]
+        style: TextStyle,
+        #[serde(default)]
+        background: Option<[f32; 4]>,
+    },
+    /// A PNG or JPEG file stretched over the area. Setting one through the control socket needs
+    /// [ControllerRole::Admin].
+    Image { path: PathBuf },
+}
+
+fn default_clock_format() -> String {
+    "%H:%M".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TextStyle {
+    /// Pango font description, like `Sans Bold 48`
+    pub font: String,
+    /// RGBA colour with each channel between 0 and 1
+    pub color: [f32; 4],
+    pub align: TextAlign,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font: "Sans 48".to_string(),
+            color: [1.0, 1.0, 1.0, 1.0],
+            align: TextAlign::Left,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// Where an application goes, relative to one of the [CompositorConfig::screens]. Screens are counted from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Slot {
+    Screen {
+        screen: usize,
+    },
+    Half {
+        screen: usize,
+        side: Side,
+    },
+    /// A cell of a grid laid over a screen. Cells can span several columns and rows.
+    Grid {
+        screen: usize,
+        columns: u32,
+        rows: u32,
+        column: u32,
+        row: u32,
+        #[serde(default = "default_span")]
+        column_span: u32,
+        #[serde(default = "default_span")]
+        row_span: u32,
+    },
+    /// Share a screen with the other automatically tiled applications on it. Goes on the screen with the
+    /// fewest tiles if `screen` isn't set. The tiles on a screen are rebalanced whenever one is added or
+    /// exits.
+    Auto {
+        #[serde(default)]
+        screen: Option<usize>,
+    },
+}
+
+fn default_span() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Rect {
     pub x: u32,
     pub y: u32,
@@ -24,23 +789,198 @@ pub struct Rect {
 }
 
 impl Rect {
+    /// Whether the point is in the rect. The left and top edges are part of the rect while the right and
+    /// bottom edges are not, so rects that touch never both contain a point.
     pub fn is_inside(&self, x: u32, y: u32) -> bool {
-        self.x < x && x < (self.x + self.width) && self.y < y && y < (self.y + self.height)
+        self.x <= x && x < (self.x + self.width) && self.y <= y && y < (self.y + self.height)
+    }
+
+    /// Whether the two rects share any area
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    /// Shrink the rect by `amount` on every side. Never shrinks past a zero sized rect.
+    pub fn inset(&self, amount: u32) -> Rect {
+        let amount_x = amount.min(self.width / 2);
+        let amount_y = amount.min(self.height / 2);
+        Rect {
+            x: self.x + amount_x,
+            y: self.y + amount_y,
+            width: self.width - amount_x * 2,
+            height: self.height - amount_y * 2,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct CompositorProcess {
-    pub child: Child,
+    /// `None` for [AppConfig::image] tiles since the compositor draws those itself
+    pub child: Option<Child>,
     pub config: AppConfig,
+    pub restarts: RestartHistory,
+    /// Counts up with every application started. Automatically tiled applications are laid out in this
+    /// order so they keep their place.
+    pub start_sequence: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RestartHistory {
+    /// Total number of times the application has been restarted
+    pub total: u32,
+    /// Restarts without the application staying up for long in between. Used for backoff.
+    pub consecutive: u32,
+    /// When the current instance of the application was started
+    pub started: Instant,
+}
+
+impl Default for RestartHistory {
+    fn default() -> Self {
+        Self {
+            total: 0,
+            consecutive: 0,
+            started: Instant::now(),
+        }
+    }
 }
 
 /// Represents commands issues by the app controller to the compositor
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum AppControllerCommand {
-    Spawn { config: AppConfig },
-    Move { pid: u32, rect: Rect },
-    Kill { pid: u32 },
+    Spawn {
+        config: AppConfig,
+    },
+    /// Spawn one of the [SpawnPolicy::apps], optionally in a different area than configured
+    SpawnApp {
+        name: String,
+        #[serde(default)]
+        area: Option<Rect>,
+    },
+    Move {
+        pid: u32,
+        rect: Rect,
+    },
+    /// Stop an application. With `graceful` its windows are asked to close first. If it's still running after
+    /// [ShutdownConfig::close_grace_ms] it's sent `signal` (SIGTERM by default), followed by SIGKILL after
+    /// [ShutdownConfig::term_grace_ms]. Without either it's killed straight away.
+    Kill {
+        pid: u32,
+        #[serde(default)]
+        graceful: bool,
+        #[serde(default)]
+        signal: Option<KillSignal>,
+    },
     List,
     ScreenSize,
+    /// Keep the connection open and push [AppControllerEvent]s down it as they happen
+    Subscribe,
+    /// Get the last `tail` lines an application printed (or everything that's buffered). With `follow` new
+    /// lines keep being sent as [AppControllerEvent::Log] afterwards, also once the application restarts.
+    Logs {
+        pid: u32,
+        #[serde(default)]
+        tail: Option<usize>,
+        #[serde(default)]
+        follow: bool,
+    },
+    /// Resources currently used by an application
+    Usage {
+        pid: u32,
+    },
+    /// Switch to one of the [CompositorConfig::layouts]. Running applications that are part of it are kept,
+    /// and the wall changes over in a single frame.
+    ApplyLayout {
+        name: String,
+    },
+    /// Add an overlay widget, or replace the one with the same [OverlayWidget::id]
+    SetOverlay {
+        widget: OverlayWidget,
+    },
+    /// Remove the overlay widget with `id`, or every widget if it's not set
+    ClearOverlay {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Image backgrounds need [ControllerRole::Admin] since they have the compositor open files
+    SetBackground {
+        background: Background,
+    },
+    /// Draw the windows of `source_pid` a second time in `rect`. Responds with the id of the mirror as
+    /// its `pid`. The mirror follows the application when it's restarted and goes away
+    /// once it exits for good.
+    Mirror {
+        source_pid: u32,
+        rect: Rect,
+        #[serde(default)]
+        input: MirrorInput,
+    },
+    /// Remove a mirror made with [AppControllerCommand::Mirror] or from [CompositorConfig::mirrors]
+    Unmirror {
+        id: u32,
+    },
+}
+
+impl AppControllerCommand {
+    /// Lowest role allowed to send the command
+    pub fn required_role(&self) -> ControllerRole {
+        match self {
+            // Pointing the compositor at a file has it open and decode anything it can read
+            AppControllerCommand::SetOverlay {
+                widget:
+                    OverlayWidget {
+                        kind: WidgetKind::Image { .. },
+                        ..
+                    },
+            } => ControllerRole::Admin,
+            AppControllerCommand::SetBackground {
+                background: Background::Image { .. } | Background::Screens { .. },
+            } => ControllerRole::Admin,
+            AppControllerCommand::List
+            | AppControllerCommand::ScreenSize
+            | AppControllerCommand::Subscribe
+            | AppControllerCommand::Usage { .. } => ControllerRole::ReadOnly,
+            // Application output can contain anything, including secrets
+            AppControllerCommand::Logs { .. }
+            | AppControllerCommand::Move { .. }
+            | AppControllerCommand::ApplyLayout { .. }
+            | AppControllerCommand::SetOverlay { .. }
+            | AppControllerCommand::ClearOverlay { .. }
+            | AppControllerCommand::SetBackground { .. }
+            | AppControllerCommand::Mirror { .. }
+            | AppControllerCommand::Unmirror { .. } => ControllerRole::Operator,
+            AppControllerCommand::Spawn { .. }
+            | AppControllerCommand::SpawnApp { .. }
+            | AppControllerCommand::Kill { .. } => ControllerRole::Admin,
+        }
+    }
+}
+
+/// Represents things that happened inside the compositor that the app controller is told about
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum AppControllerEvent {
+    /// An application exited, either on its own or because it was killed. `code` is set if it exited
+    /// normally, `signal` is set if it was terminated by a signal.
+    Exited {
+        pid: u32,
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+    /// A line of output from an application being followed with [AppControllerCommand::Logs]
+    Log { pid: u32, line: LogLine },
+    /// An application was started again after exiting or hanging and carries on as `new_pid`
+    Restarted { pid: u32, new_pid: u32 },
+    /// An application opened its first window
+    WindowMapped { pid: u32 },
+    /// An application didn't answer a ping within [PingConfig::timeout_ms]
+    NotResponding { pid: u32 },
+    /// An application that was [AppControllerEvent::NotResponding] answered again
+    Responding { pid: u32 },
+    /// The wall switched to its attract layout ([IdleConfig::attract]) or back from it
+    IdleChanged { idle: bool },
+    /// An application from [CompositorConfig::launch] could not be started. `name` is
+    /// [AppConfig::name] if it has one, otherwise [AppConfig::executable].
+    LaunchFailed { name: String, error: String },
 }
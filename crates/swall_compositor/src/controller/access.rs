@@ -0,0 +1,47 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use tokio::net::UnixStream;
+
+use crate::config::{ControllerAccess, ControllerRole};
+
+/// Works out what the process on the other end of a control socket connection may do from its credentials
+pub fn client_role(stream: &UnixStream, access: &ControllerAccess) -> Result<ControllerRole> {
+    let credentials = stream.peer_cred()?;
+    let uid = credentials.uid();
+
+    // SAFETY: getuid has no preconditions and can't fail
+    let compositor_uid = unsafe { libc::getuid() };
+    if uid == 0 || uid == compositor_uid {
+        return Ok(ControllerRole::Admin);
+    }
+
+    let mut gids = vec![credentials.gid()];
+    if let Some(pid) = credentials.pid() {
+        gids.extend(supplementary_groups(pid));
+    }
+
+    access.role_of(uid, &gids).ok_or_else(|| {
+        anyhow!(
+            "uid {uid} (pid {:?}) is not allowed to use the controller",
+            credentials.pid()
+        )
+    })
+}
+
+/// Supplementary groups of a process from the `Groups:` line in `/proc/<pid>/status`
+fn supplementary_groups(pid: i32) -> Vec<u32> {
+    fs::read_to_string(format!("/proc/{pid}/status"))
+        .ok()
+        .and_then(|status| {
+            Some(
+                status
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Groups:"))?
+                    .split_whitespace()
+                    .filter_map(|gid| gid.parse().ok())
+                    .collect(),
+            )
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Result};
+
+use crate::config::AppConfig;
+
+use super::socket::CONTROLLER_SOCKET;
+
+/// Builds the [Command] that starts an application, applying everything from its [AppConfig].
+///
+/// `{x}`, `{y}`, `{width}`, `{height}`, `{screen_width}`, `{screen_height}` and `{app_id}` in the args and
+/// env values are replaced with the area the application is given, the canvas size and the app's name.
+pub fn build_command(
+    app_config: &AppConfig,
+    wayland_socket: &Path,
+    screen_size: [u32; 2],
+) -> Result<Command> {
+    // The app only gets the space inside of its border
+    let area = match app_config.border {
+        Some(border) => app_config.area.inset(border.width),
+        None => app_config.area,
+    };
+    let placeholders = [
+        ("{x}", area.x.to_string()),
+        ("{y}", area.y.to_string()),
+        ("{width}", area.width.to_string()),
+        ("{height}", area.height.to_string()),
+        ("{screen_width}", screen_size[0].to_string()),
+        ("{screen_height}", screen_size[1].to_string()),
+        ("{app_id}", app_id(app_config)),
+    ];
+    let expand = |value: &str| {
+        placeholders
+            .iter()
+            .fold(value.to_string(), |value, (placeholder, replacement)| {
+                value.replace(placeholder, replacement)
+            })
+    };
+
+    let mut command = Command::new(app_config.executable.as_str());
+    command
+        .args(app_config.args.iter().map(|arg| expand(arg)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    if app_config.clear_env {
+        command.env_clear();
+    }
+
+    if let Some(user) = &app_config.user {
+        let user = lookup_user(user)?;
+        command.uid(user.uid).gid(user.gid);
+        // What a login would set. Can still be overridden through [AppConfig::env].
+        command
+            .env("HOME", &user.home)
+            .env("USER", &user.name)
+            .env("LOGNAME", &user.name);
+    }
+
+    // Applied after the user so it overrides the user's primary group
+    if let Some(group) = &app_config.group {
+        command.gid(lookup_group(group)?);
+    }
+
+    if let Some(cwd) = &app_config.cwd {
+        command.current_dir(cwd);
+    }
+
+    command.envs(
+        app_config
+            .env
+            .iter()
+            .map(|(key, value)| (key, expand(value))),
+    );
+
+    // Always last so apps can't be pointed away from the swall
+    command
+        .env("WAYLAND_DISPLAY", wayland_socket.file_name().unwrap())
+        .env("XDG_RUNTIME_DIR", wayland_socket.parent().unwrap())
+        .env("SWALL_X", area.x.to_string())
+        .env("SWALL_Y", area.y.to_string())
+        .env("SWALL_WIDTH", area.width.to_string())
+        .env("SWALL_HEIGHT", area.height.to_string())
+        .env("SWALL_CONTROL_SOCKET", CONTROLLER_SOCKET);
+
+    Ok(command)
+}
+
+/// [AppConfig::name] or the file name of the executable for apps without one
+fn app_id(app_config: &AppConfig) -> String {
+    app_config.name.clone().unwrap_or_else(|| {
+        Path::new(&app_config.executable)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| app_config.executable.clone())
+    })
+}
+
+#[derive(Debug)]
+struct User {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: String,
+}
+
+/// Find a user in `/etc/passwd` by name or uid
+fn lookup_user(user: &str) -> Result<User> {
+    let passwd = fs::read_to_string("/etc/passwd")?;
+    passwd
+        .lines()
+        .filter_map(|line| {
+            // name:password:uid:gid:gecos:home:shell
+            let fields: Vec<&str> = line.split(':').collect();
+            Some(User {
+                name: fields.first()?.to_string(),
+                uid: fields.get(2)?.parse().ok()?,
+                gid: fields.get(3)?.parse().ok()?,
+                home: fields.get(5)?.to_string(),
+            })
+        })
+        .find(|entry| entry.name == user || entry.uid.to_string() == user)
+        .ok_or_else(|| anyhow!("Unknown user '{user}'"))
+}
+
+/// Find a group's gid in `/etc/group` by name or gid
+fn lookup_group(group: &str) -> Result<u32> {
+    let groups = fs::read_to_string("/etc/group")?;
+    groups
+        .lines()
+        .filter_map(|line| {
+            // name:password:gid:members
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let gid: u32 = fields.nth(1)?.parse().ok()?;
+            Some((name, gid))
+        })
+        .find(|(name, gid)| *name == group || gid.to_string() == group)
+        .map(|(_, gid)| gid)
+        .ok_or_else(|| anyhow!("Unknown group '{group}'"))
+}
@@ -1,30 +1,332 @@
-use std::collections::HashMap;
-use std::{path::Path, process::Command};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, mpsc, Mutex};
 
 use crate::compositor::CompositorApplicationHandle;
-use crate::config::{AppConfig, CompositorProcess, Rect};
+use crate::config::{
+    AppConfig, AppControllerEvent, Background, CompositorConfig, CompositorProcess, ImageTile,
+    KillSignal, LogLine, Mirror, MirrorConfig, MirrorInput, OverlayWidget, Rect, ResourceUsage,
+    RestartHistory, RestartPolicy, ShutdownConfig, Side, Slot, SpawnPolicy,
+};
+
+use super::command::build_command;
+use super::limits::{apply_limits, prepare_cgroup_root, process_usage, AppCgroup};
+use super::logs::{capture_output, AppLogs};
+use super::policy::{audit_spawn, check_spawn};
+use super::tiling::{reserve_edge, screen_rect, slot_rect, tile_rects};
+
+/// How often children are checked to see if they have exited
+const REAP_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Longest time new applications of a layout are kept hidden while waiting for their windows
+const LAYOUT_WINDOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a process that was asked to stop is checked for having exited
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Longest time to wait for a process to go away after SIGKILL before leaving it to the reaper
+const KILL_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Image tiles are numbered from here. Pids on Linux never go past 2^22, so the two can't be mixed up.
+const FIRST_IMAGE_TILE_ID: u32 = 1 << 31;
+
+/// Number of exited applications whose logs are kept around so crashes can be looked into
+const EXITED_LOGS_KEPT: usize = 16;
 
 #[derive(Debug)]
 pub struct AppController {
     // TODO: Is it a good idea for this mutex to be in here?
     child_processes: Mutex<HashMap<u32, CompositorProcess>>,
     compositor_app_handle: CompositorApplicationHandle,
+    /// Dimensions of the overall compositor canvas
+    screen_size: [u32; 2],
+    /// The physical screens making up the canvas
+    screens: Vec<Rect>,
+    /// What's left of each screen for automatically tiled applications once the exclusive zones of layer
+    /// shell surfaces are taken out
+    tiling_areas: Mutex<Vec<Rect>>,
+    /// Restricts what can be spawned through the control socket
+    spawn_policy: SpawnPolicy,
+    events: broadcast::Sender<AppControllerEvent>,
+    /// Applications that exited and are waiting out their backoff before being started again
+    pending_restarts: Mutex<Vec<PendingRestart>>,
+    /// Captured output by pid. Entries for exited processes are kept until [EXITED_LOGS_KEPT] newer ones exit.
+    logs: Mutex<HashMap<u32, Arc<AppLogs>>>,
+    exited_logs: Mutex<VecDeque<u32>>,
+    /// Delegated cgroup v2 subtree that app cgroups are made in, if there is one
+    cgroup_root: Option<PathBuf>,
+    cgroups: Mutex<HashMap<u32, AppCgroup>>,
+    /// Used to give every app cgroup a unique name
+    cgroup_counter: AtomicU32,
+    /// Id given to the next image tile
+    next_image_tile_id: AtomicU32,
+    /// [CompositorProcess::start_sequence] of the next application to start
+    next_start_sequence: AtomicU64,
+    /// Mirrors made for applications when they start, by [AppConfig::name]
+    mirrors: Vec<MirrorConfig>,
+    /// Id given to the next mirror
+    next_mirror_id: AtomicU32,
+    /// Applications that stopped answering pings
+    not_responding: Mutex<HashSet<u32>>,
+    /// Applications being stopped on purpose. They aren't restarted when they exit.
+    stopping: Mutex<HashSet<u32>>,
+    shutdown: ShutdownConfig,
+    /// The applications that were running before switching to the attract layout. Only set while idle.
+    interactive_layout: Mutex<Option<Vec<AppConfig>>>,
+    layouts: BTreeMap<String, Vec<AppConfig>>,
+}
+
+#[derive(Debug)]
+struct PendingRestart {
+    /// Pid of the run that exited
+    pid: u32,
+    at: Instant,
+    config: AppConfig,
+    restarts: RestartHistory,
+    logs: Option<Arc<AppLogs>>,
 }
 
 impl AppController {
-    pub fn new(compositor_app_handle: CompositorApplicationHandle) -> Self {
+    pub fn new(
+        compositor_app_handle: CompositorApplicationHandle,
+        config: &CompositorConfig,
+    ) -> Self {
+        let cgroup_root =
+            config
+                .cgroup_root
+                .clone()
+                .filter(|root| match prepare_cgroup_root(root) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        println!(
+                    "Can't use cgroup '{}', limits will only be partially enforced: {error}",
+                    root.display()
+                );
+                        false
+                    }
+                });
+
+        // Has room for log lines from a chatty app without followers falling behind too quickly
+        let (events, _) = broadcast::channel(256);
         Self {
             child_processes: Default::default(),
             compositor_app_handle,
+            screen_size: [config.width, config.height],
+            screens: config.screen_rects(),
+            tiling_areas: Mutex::new(config.screen_rects()),
+            spawn_policy: config.spawn_policy.clone(),
+            events,
+            pending_restarts: Default::default(),
+            logs: Default::default(),
+            exited_logs: Default::default(),
+            cgroup_root,
+            cgroups: Default::default(),
+            cgroup_counter: AtomicU32::new(0),
+            next_image_tile_id: AtomicU32::new(FIRST_IMAGE_TILE_ID),
+            next_start_sequence: AtomicU64::new(0),
+            mirrors: config.mirrors.clone(),
+            next_mirror_id: AtomicU32::new(0),
+            not_responding: Default::default(),
+            stopping: Default::default(),
+            shutdown: config.shutdown,
+            interactive_layout: Default::default(),
+            layouts: config.layouts.clone(),
         }
     }
 
-    /// Spawns a process from an [AppConfig] and redirects it's display variables to the swall Wayland Socket
+    /// Receive [AppControllerEvent]s from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<AppControllerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Called by the compositor when an application opens a window. Must not be called from async code.
+    pub fn notify_window_mapped(&self, pid: u32) {
+        // A restarted application is covered until it can show itself again
+        self.compositor_app_handle
+            .remove_application_placeholder_blocking(pid);
+        let _ = self.events.send(AppControllerEvent::WindowMapped { pid });
+    }
+
+    /// Called by the compositor when an application stops or starts answering pings again. Applies the
+    /// application's [crate::config::UnresponsivePolicy].
+    pub async fn set_responsive(&self, pid: u32, responsive: bool) {
+        let Some(policy) = self
+            .child_processes
+            .lock()
+            .await
+            .get(&pid)
+            .map(|process| process.config.unresponsive)
+        else {
+            // Not one of ours
+            return;
+        };
+
+        if responsive {
+            if !self.not_responding.lock().await.remove(&pid) {
+                return;
+            }
+            println!("Process {pid} is responding again");
+            self.compositor_app_handle
+                .set_application_placeholder(pid, None)
+                .await;
+            let _ = self.events.send(AppControllerEvent::Responding { pid });
+            return;
+        }
+
+        if !self.not_responding.lock().await.insert(pid) {
+            return;
+        }
+        println!("Process {pid} is not responding");
+        self.compositor_app_handle
+            .set_application_placeholder(pid, policy.placeholder)
+            .await;
+        let _ = self.events.send(AppControllerEvent::NotResponding { pid });
+
+        if policy.restart {
+            if let Err(error) = self.restart_unresponsive(pid).await {
+                println!("Failed to restart unresponsive process {pid}: {error}");
+            }
+        }
+    }
+
+    /// Kill a hung application and start it again straight away in the same rect
+    async fn restart_unresponsive(&self, pid: u32) -> Result<()> {
+        let Some(mut process) = self.child_processes.lock().await.remove(&pid) else {
+            return Ok(());
+        };
+        let status = kill_and_wait(&mut process).await?;
+        let config = process.config.clone();
+        let restarts = RestartHistory {
+            total: process.restarts.total + 1,
+            consecutive: process.restarts.consecutive + 1,
+            started: Instant::now(),
+        };
+        if status.is_some() {
+            self.compositor_app_handle
+                .remove_application_position(pid)
+                .await;
+        } else {
+            self.leave_to_reaper(pid, process).await;
+        }
+
+        let logs = self.logs.lock().await.get(&pid).cloned();
+        let restarted = self
+            .spawn_process_with_history(&config, restarts, logs, false)
+            .await;
+        // Mirrors are moved over before the old pid is cleaned up, which would drop them
+        if let Ok(new_pid) = restarted {
+            self.compositor_app_handle
+                .retarget_mirrors(pid, new_pid)
+                .await;
+            let _ = self
+                .events
+                .send(AppControllerEvent::Restarted { pid, new_pid });
+        }
+        if let Some(status) = status {
+            self.notify_exit(pid, status).await;
+        }
+        let new_pid = restarted?;
+        println!(
+            "Restarted unresponsive {} as pid {new_pid}",
+            config.executable
+        );
+
+        Ok(())
+    }
+
+    /// Let everyone know an application from the launch config could not be started
+    pub fn report_launch_failure(&self, name: String, error: String) {
+        println!("Failed to launch {name}: {error}");
+        let _ = self
+            .events
+            .send(AppControllerEvent::LaunchFailed { name, error });
+    }
+
+    /// Spawns a process from an [AppConfig] and redirects it's display variables to the swall Wayland Socket.
+    /// The environment, working directory and user are set up as described by the [AppConfig].
     pub async fn spawn_process(&self, app_config: &AppConfig) -> Result<u32> {
+        self.spawn_process_with_history(app_config, RestartHistory::default(), None, false)
+            .await
+    }
+
+    /// Spawn requested through the control socket. Has to be allowed by the [SpawnPolicy] and is audited.
+    pub async fn spawn_requested(&self, app_config: &AppConfig) -> Result<u32> {
+        if let Err(error) = check_spawn(&self.spawn_policy, app_config) {
+            audit_spawn(
+                &self.spawn_policy,
+                None,
+                Some(app_config),
+                Err(&error),
+                true,
+            );
+            return Err(error);
+        }
+
+        let result = self.spawn_process(app_config).await;
+        audit_spawn(
+            &self.spawn_policy,
+            None,
+            Some(app_config),
+            result.as_ref().copied(),
+            false,
+        );
+        result
+    }
+
+    /// Spawn one of the trusted [SpawnPolicy::apps], optionally in a different area than configured.
+    /// Returns the config that was spawned.
+    pub async fn spawn_named(&self, name: &str, area: Option<Rect>) -> Result<(u32, AppConfig)> {
+        let Some(app_config) = self.spawn_policy.apps.get(name) else {
+            let error = anyhow!("No app named '{name}' in the spawn policy");
+            audit_spawn(&self.spawn_policy, Some(name), None, Err(&error), true);
+            return Err(error);
+        };
+
+        let mut app_config = app_config.clone();
+        if let Some(area) = area {
+            app_config.area = area;
+        }
+
+        let result = self.spawn_process(&app_config).await;
+        audit_spawn(
+            &self.spawn_policy,
+            Some(name),
+            Some(&app_config),
+            result.as_ref().copied(),
+            false,
+        );
+        result.map(|pid| (pid, app_config))
+    }
+
+    /// Restarted applications pass in the logs of their previous run so they carry on in the same buffer.
+    /// `hidden` applications aren't shown until they are placed by [CompositorApplicationHandle::switch_layout].
+    async fn spawn_process_with_history(
+        &self,
+        app_config: &AppConfig,
+        restarts: RestartHistory,
+        logs: Option<Arc<AppLogs>>,
+        hidden: bool,
+    ) -> Result<u32> {
+        let app_config = &self.place(app_config).await?;
+        if let Some(image) = &app_config.image {
+            return self
+                .show_image_tile(app_config, image, restarts, hidden)
+                .await;
+        }
+
+        let logs = match logs {
+            Some(logs) => logs,
+            None => Arc::new(AppLogs::new(&app_config.log)?),
+        };
+
         let wayland_socket: &Path = Path::new("/tmp/swall/wayland-0");
 
         let process = {
@@ -33,29 +335,255 @@ impl AppController {
 
             println!("Spawning {}", app_config.executable);
 
-            let process = Command::new(app_config.executable.as_str())
-                .args(app_config.args.iter())
-                .env("WAYLAND_DISPLAY", wayland_socket.file_name().unwrap())
-                .env("XDG_RUNTIME_DIR", wayland_socket.parent().unwrap())
-                .spawn()?;
+            let mut command = build_command(app_config, wayland_socket, self.screen_size)?;
+            let cgroup = match &self.cgroup_root {
+                Some(root) => {
+                    let name = format!(
+                        "app-{}",
+                        self.cgroup_counter.fetch_add(1, Ordering::Relaxed)
+                    );
+                    Some(AppCgroup::create(root, &name, &app_config.limits)?)
+                }
+                None => None,
+            };
+            apply_limits(&mut command, &app_config.limits, cgroup.as_ref())?;
+
+            let mut process = match command.spawn() {
+                Ok(process) => process,
+                Err(error) => {
+                    if let Some(cgroup) = cgroup {
+                        cgroup.remove();
+                    }
+                    return Err(error.into());
+                }
+            };
+            if let Some(cgroup) = cgroup {
+                if let Err(error) = cgroup.add_process(process.id()) {
+                    println!("Failed to move {} into its cgroup: {error}", process.id());
+                }
+                self.cgroups.lock().await.insert(process.id(), cgroup);
+            }
+
+            capture_output(
+                process.id(),
+                process.stdout.take(),
+                process.stderr.take(),
+                logs.clone(),
+                self.events.clone(),
+            );
 
             positioner_guard.set_application_position(process.id(), app_config.area);
+            positioner_guard.set_application_border(process.id(), app_config.border);
+            if hidden {
+                positioner_guard.hide_application(process.id());
+            }
+            if restarts.total > 0 {
+                positioner_guard
+                    .set_application_placeholder(process.id(), app_config.unresponsive.placeholder);
+            }
 
             process
         };
 
         let pid = process.id();
+        let first_run = restarts.total == 0;
+        self.logs.lock().await.insert(pid, logs);
         self.child_processes.lock().await.insert(
             pid,
             CompositorProcess {
-                child: process,
+                child: Some(process),
                 config: app_config.clone(),
+                restarts,
+                start_sequence: self.next_start_sequence.fetch_add(1, Ordering::Relaxed),
             },
         );
 
+        // Restarted applications keep the mirrors of their previous run
+        if first_run {
+            let mirrors = self
+                .mirrors
+                .iter()
+                .filter(|mirror| app_config.name.as_ref() == Some(&mirror.source));
+            for mirror in mirrors {
+                self.add_mirror(Mirror {
+                    source_pid: pid,
+                    rect: mirror.rect,
+                    input: mirror.input,
+                })
+                .await;
+            }
+        }
+
+        // Hidden applications are tiled along with the rest of their layout
+        if !hidden && matches!(app_config.slot, Some(Slot::Auto { .. })) {
+            self.rebalance().await;
+        }
+
         Ok(pid)
     }
 
+    /// Have the compositor draw an [AppConfig::image] tile. It's tracked like any other application, under
+    /// an id that can't clash with a real pid.
+    async fn show_image_tile(
+        &self,
+        app_config: &AppConfig,
+        image: &ImageTile,
+        restarts: RestartHistory,
+        hidden: bool,
+    ) -> Result<u32> {
+        if image.images.is_empty() {
+            return Err(anyhow!("Image tile has no images"));
+        }
+
+        let id = self.next_image_tile_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut positioner_guard = self.compositor_app_handle.reserve().await;
+            positioner_guard.set_application_position(id, app_config.area);
+            positioner_guard.set_application_border(id, app_config.border);
+            positioner_guard.set_image_tile(id, image.clone());
+            if hidden {
+                positioner_guard.hide_application(id);
+            }
+        }
+        println!("Showing image tile {id}");
+
+        self.child_processes.lock().await.insert(
+            id,
+            CompositorProcess {
+                child: None,
+                config: app_config.clone(),
+                restarts,
+                start_sequence: self.next_start_sequence.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+
+        if !hidden && matches!(app_config.slot, Some(Slot::Auto { .. })) {
+            self.rebalance().await;
+        }
+
+        Ok(id)
+    }
+
+    /// Works out [AppConfig::area] from [AppConfig::slot]. Automatically tiled applications are given a
+    /// screen and the last tile of it, which is where they end up once the screen is rebalanced.
+    async fn place(&self, app_config: &AppConfig) -> Result<AppConfig> {
+        let mut app_config = app_config.clone();
+        match app_config.slot {
+            None => {}
+            Some(Slot::Auto { screen }) => {
+                let mut tiles = HashMap::new();
+                for process in self.child_processes.lock().await.values() {
+                    if let Some(Slot::Auto {
+                        screen: Some(screen),
+                    }) = process.config.slot
+                    {
+                        *tiles.entry(screen).or_insert(0) += 1;
+                    }
+                }
+                let tiles_on = |screen: &usize| tiles.get(screen).copied().unwrap_or(0);
+
+                let screen = match screen {
+                    Some(screen) => screen,
+                    None => (0..self.screens.len()).min_by_key(tiles_on).unwrap_or(0),
+                };
+                let count = tiles_on(&screen) + 1;
+                let region = screen_rect(&self.tiling_areas.lock().await, screen)?;
+                app_config.area = tile_rects(region, count)[count - 1];
+                app_config.slot = Some(Slot::Auto {
+                    screen: Some(screen),
+                });
+            }
+            Some(slot) => app_config.area = slot_rect(slot, &self.screens)?,
+        }
+        Ok(app_config)
+    }
+
+    /// Where every automatically tiled application should be so each screen is shared evenly, leaving out
+    /// `excluding`. Only returns the ones that have to move.
+    async fn auto_tile_rects(&self, excluding: &[u32]) -> Vec<(u32, Rect)> {
+        let tiling_areas = self.tiling_areas.lock().await.clone();
+        let mut by_screen: BTreeMap<usize, Vec<(u64, u32, Rect)>> = BTreeMap::new();
+        for (pid, process) in self.child_processes.lock().await.iter() {
+            if let Some(Slot::Auto {
+                screen: Some(screen),
+            }) = process.config.slot
+            {
+                if !excluding.contains(pid) {
+                    by_screen.entry(screen).or_default().push((
+                        process.start_sequence,
+                        *pid,
+                        process.config.area,
+                    ));
+                }
+            }
+        }
+
+        let mut moves = Vec::new();
+        for (screen, mut tiles) in by_screen {
+            let Ok(region) = screen_rect(&tiling_areas, screen) else {
+                continue;
+            };
+            // Oldest first so applications keep their place as others come and go
+            tiles.sort_by_key(|(start_sequence, ..)| *start_sequence);
+            for ((_, pid, area), rect) in tiles.iter().zip(tile_rects(region, tiles.len())) {
+                if *area != rect {
+                    moves.push((*pid, rect));
+                }
+            }
+        }
+        moves
+    }
+
+    /// Move automatically tiled applications so they share their screens evenly again
+    async fn rebalance(&self) {
+        for (pid, rect) in self.auto_tile_rects(&[]).await {
+            if let Err(error) = self.resize_process(&pid, &rect).await {
+                println!("Failed to move {pid} while rebalancing: {error}");
+            }
+        }
+    }
+
+    /// Take the exclusive zones of layer shell surfaces out of the space automatically tiled applications
+    /// get. Each zone is the rect the surface is anchored in, the edge it's anchored to and how much of
+    /// that edge it reserves.
+    pub async fn set_exclusive_zones(&self, zones: &[(Rect, Side, u32)]) {
+        let areas: Vec<Rect> = self
+            .screens
+            .iter()
+            .map(|screen| {
+                zones
+                    .iter()
+                    .fold(*screen, |area, (container, side, amount)| {
+                        reserve_edge(area, *container, *side, *amount)
+                    })
+            })
+            .collect();
+
+        {
+            let mut tiling_areas = self.tiling_areas.lock().await;
+            if *tiling_areas == areas {
+                return;
+            }
+            *tiling_areas = areas;
+        }
+        self.rebalance().await;
+    }
+
+    /// [AppConfig::name] of an application, or its [AppConfig::executable] if it doesn't have one. Only for
+    /// use outside of async code.
+    pub fn app_name_blocking(&self, pid: u32) -> Option<String> {
+        self.child_processes
+            .blocking_lock()
+            .get(&pid)
+            .map(|process| {
+                process
+                    .config
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| process.config.executable.clone())
+            })
+    }
+
     /// resizes a process window from a rect and updates the state
     pub async fn resize_process(&self, pid: &u32, rect: &Rect) -> Result<u32> {
         if let Some(compositor_process) = self.child_processes.lock().await.get_mut(pid) {
@@ -75,11 +603,18 @@ impl AppController {
     /// Kills a process by a u32 process Id
     /// Current processes spawned by the compositor can be obtained by using the [list_processes] function
     pub async fn kill_process(&self, pid: u32) -> Result<()> {
-        if let Some(mut child_process) = self.child_processes.lock().await.remove(&pid) {
-            child_process.child.kill()?;
+        let child_process = self.child_processes.lock().await.remove(&pid);
+        if let Some(mut child_process) = child_process {
+            // Wait on the child so it doesn't linger as a zombie. SIGKILL can't be ignored so this is usually
+            // quick, but a process stuck in the kernel only goes away once it gets out.
+            let Some(status) = kill_and_wait(&mut child_process).await? else {
+                self.leave_to_reaper(pid, child_process).await;
+                return Ok(());
+            };
             self.compositor_app_handle
                 .remove_application_position(pid)
                 .await;
+            self.notify_exit(pid, status).await;
 
             Ok(())
         } else {
@@ -87,6 +622,577 @@ impl AppController {
         }
     }
 
+    /// Replace every running application with `apps`. Returns the configs of the applications that were
+    /// running so they can be brought back later.
+    pub async fn switch_apps(self: &Arc<Self>, apps: &[AppConfig]) -> Vec<AppConfig> {
+        let running = self.list_staying().await;
+        self.apply_layout(apps).await;
+        running.into_iter().map(|(_, config)| config).collect()
+    }
+
+    /// Show one of the [CompositorConfig::layouts]. While idle it is shown once someone touches the wall.
+    pub async fn apply_named_layout(self: &Arc<Self>, name: &str) -> Result<()> {
+        let Some(apps) = self.layouts.get(name) else {
+            return Err(anyhow!("No layout named '{name}'"));
+        };
+        println!("Switching to layout '{name}'");
+        self.set_interactive_layout(apps.clone()).await;
+        Ok(())
+    }
+
+    /// Add an overlay widget, or replace the one with the same id
+    pub async fn set_overlay(&self, widget: OverlayWidget) {
+        self.compositor_app_handle.set_overlay(widget).await;
+    }
+
+    /// Remove the overlay widget with `id`, or every widget if `id` is `None`
+    pub async fn clear_overlay(&self, id: Option<&str>) -> Result<()> {
+        let removed = self.compositor_app_handle.clear_overlay(id).await;
+        match id {
+            Some(id) if !removed => Err(anyhow!("No overlay widget with id '{id}'")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Change what's drawn behind everything else
+    pub async fn set_background(&self, background: Background) {
+        self.compositor_app_handle.set_background(background).await;
+    }
+
+    /// Draw the windows of `source_pid` again in `rect`. Returns the id of the mirror.
+    pub async fn mirror(&self, source_pid: u32, rect: Rect, input: MirrorInput) -> Result<u32> {
+        match self.child_processes.lock().await.get(&source_pid) {
+            Some(process) if process.child.is_none() => {
+                return Err(anyhow!(
+                    "{source_pid} is an image tile, which has no windows to mirror"
+                ))
+            }
+            Some(_) => {}
+            None => return Err(anyhow!("pid {source_pid} not found")),
+        }
+        Ok(self
+            .add_mirror(Mirror {
+                source_pid,
+                rect,
+                input,
+            })
+            .await)
+    }
+
+    async fn add_mirror(&self, mirror: Mirror) -> u32 {
+        let id = self.next_mirror_id.fetch_add(1, Ordering::Relaxed);
+        self.compositor_app_handle.add_mirror(id, mirror).await;
+        println!("Mirroring {} as mirror {id}", mirror.source_pid);
+        id
+    }
+
+    pub async fn unmirror(&self, id: u32) -> Result<()> {
+        match self.compositor_app_handle.remove_mirror(id).await {
+            true => Ok(()),
+            false => Err(anyhow!("No mirror with id {id}")),
+        }
+    }
+
+    /// Make the running applications match `apps`. Running applications that are the same as one in the
+    /// layout are kept and moved into place, the rest are stopped and anything missing is started. New
+    /// applications stay hidden until their windows are open (or [LAYOUT_WINDOW_TIMEOUT] passes) so the
+    /// whole wall changes in a single frame.
+    async fn apply_layout(self: &Arc<Self>, apps: &[AppConfig]) {
+        // The layout is everything that should be on the wall, so nothing else should come back either
+        let abandoned: Vec<PendingRestart> = self.pending_restarts.lock().await.drain(..).collect();
+        for pending in abandoned {
+            self.compositor_app_handle
+                .remove_mirrors_of(pending.pid)
+                .await;
+        }
+
+        let mut running = self.list_staying().await;
+        let mut placed = Vec::new();
+        let mut reused = Vec::new();
+        let mut missing = Vec::new();
+        for app_config in apps {
+            match running
+                .iter()
+                .position(|(_, config)| is_same_app(config, app_config))
+            {
+                Some(index) => {
+                    let (pid, running_config) = running.swap_remove(index);
+                    let mut app_config = app_config.clone();
+                    // An automatically tiled application stays on its screen unless told otherwise
+                    if let (
+                        Some(Slot::Auto { screen: None }),
+                        Some(Slot::Auto {
+                            screen: Some(screen),
+                        }),
+                    ) = (app_config.slot, running_config.slot)
+                    {
+                        app_config.slot = Some(Slot::Auto {
+                            screen: Some(screen),
+                        });
+                    }
+                    placed.push(pid);
+                    reused.push((pid, app_config));
+                }
+                None => missing.push(app_config),
+            }
+        }
+        let removed: Vec<u32> = running.into_iter().map(|(pid, _)| pid).collect();
+
+        // Subscribe before spawning so no window can be missed
+        let mut events = self.subscribe();
+        let mut waiting = HashSet::new();
+        for app_config in missing {
+            match self
+                .spawn_process_with_history(app_config, RestartHistory::default(), None, true)
+                .await
+            {
+                Ok(pid) => {
+                    // Image tiles don't open a window, they can be shown straight away
+                    if app_config.image.is_none() {
+                        waiting.insert(pid);
+                    }
+                    placed.push(pid);
+                }
+                Err(error) => println!("Failed to start {}: {error}", app_config.executable),
+            }
+        }
+
+        let _ = tokio::time::timeout(LAYOUT_WINDOW_TIMEOUT, async {
+            while !waiting.is_empty() {
+                match events.recv().await {
+                    Ok(
+                        AppControllerEvent::WindowMapped { pid }
+                        | AppControllerEvent::Exited { pid, .. },
+                    ) => {
+                        waiting.remove(&pid);
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        })
+        .await;
+        if !waiting.is_empty() {
+            println!("Windows of {waiting:?} didn't open in time. Showing the layout anyway.");
+        }
+
+        // Reused applications take on the placement from the layout
+        for (pid, app_config) in &mut reused {
+            match self.place(app_config).await {
+                Ok(placed) => *app_config = placed,
+                Err(error) => println!("Can't place {pid} as the layout asks: {error}"),
+            }
+        }
+        let retiled = self.auto_tile_rects(&removed).await;
+        let placements: Vec<_> = {
+            let mut child_processes = self.child_processes.lock().await;
+            for (pid, app_config) in &reused {
+                if let Some(process) = child_processes.get_mut(pid) {
+                    process.config.area = app_config.area;
+                    process.config.border = app_config.border;
+                    process.config.slot = app_config.slot;
+                }
+            }
+            for (pid, rect) in &retiled {
+                if let Some(process) = child_processes.get_mut(pid) {
+                    process.config.area = *rect;
+                }
+            }
+            placed
+                .iter()
+                .filter_map(|pid| {
+                    let config = &child_processes.get(pid)?.config;
+                    Some((*pid, config.area, config.border))
+                })
+                .collect()
+        };
+        self.compositor_app_handle
+            .switch_layout(&placements, &removed)
+            .await;
+
+        // They're already off the wall, so they can take their time closing without holding up the switch.
+        // They're marked as stopping straight away so they aren't restarted or reused in the meantime.
+        self.stopping.lock().await.extend(&removed);
+        for pid in removed {
+            let app_controller = self.clone();
+            tokio::spawn(async move {
+                if let Err(error) = app_controller
+                    .stop_process(pid, true, KillSignal::Term)
+                    .await
+                {
+                    println!("Failed to stop {pid}: {error}");
+                }
+            });
+        }
+    }
+
+    /// [AppController::list_processes] without the ones that are being stopped
+    async fn list_staying(&self) -> Vec<(u32, AppConfig)> {
+        let stopping = self.stopping.lock().await.clone();
+        let mut processes = self.list_processes().await;
+        processes.retain(|(pid, _)| !stopping.contains(pid));
+        processes
+    }
+
+    /// Show `apps` instead of what is running now. While idle they are shown once someone touches the wall.
+    pub async fn set_interactive_layout(self: &Arc<Self>, apps: Vec<AppConfig>) {
+        let mut interactive_layout = self.interactive_layout.lock().await;
+        match &mut *interactive_layout {
+            Some(saved) => *saved = apps,
+            None => self.apply_layout(&apps).await,
+        }
+    }
+
+    /// Nobody is using the wall. Swap whatever is running for the attract layout.
+    pub async fn enter_idle(self: &Arc<Self>, attract: &[AppConfig]) {
+        let mut interactive_layout = self.interactive_layout.lock().await;
+        if interactive_layout.is_some() {
+            return;
+        }
+
+        println!("No interaction for a while. Switching to the attract layout.");
+        *interactive_layout = Some(self.switch_apps(attract).await);
+        let _ = self
+            .events
+            .send(AppControllerEvent::IdleChanged { idle: true });
+    }
+
+    /// Someone touched the wall. Bring back what was running before going idle.
+    pub async fn leave_idle(self: &Arc<Self>) {
+        let Some(interactive_layout) = self.interactive_layout.lock().await.take() else {
+            return;
+        };
+
+        println!("Interaction while idle. Restoring the interactive layout.");
+        self.switch_apps(&interactive_layout).await;
+        let _ = self
+            .events
+            .send(AppControllerEvent::IdleChanged { idle: false });
+    }
+
+    /// Stop a process in stages as described by [AppControllerCommand::Kill](crate::config::AppControllerCommand::Kill).
+    /// Returns once the process is gone.
+    pub async fn stop_process(&self, pid: u32, graceful: bool, signal: KillSignal) -> Result<()> {
+        let is_image_tile = match self.child_processes.lock().await.get(&pid) {
+            Some(process) => process.child.is_none(),
+            None => return Err(anyhow!("pid {pid} not found")),
+        };
+        // There is nothing to stop gracefully
+        if is_image_tile {
+            return self.kill_process(pid).await;
+        }
+        self.stopping.lock().await.insert(pid);
+
+        if graceful {
+            self.compositor_app_handle.request_close(pid).await;
+            let close_grace = Duration::from_millis(self.shutdown.close_grace_ms);
+            if self.wait_for_exit(pid, close_grace).await? {
+                return Ok(());
+            }
+        }
+
+        if signal != KillSignal::Kill {
+            send_signal(pid, signal)?;
+            let term_grace = Duration::from_millis(self.shutdown.term_grace_ms);
+            if self.wait_for_exit(pid, term_grace).await? {
+                return Ok(());
+            }
+        }
+
+        println!("Process {pid} didn't stop in time. Killing it.");
+        match self.kill_process(pid).await {
+            // It exited just before being killed and the reaper got to it
+            Err(_) if !self.child_processes.lock().await.contains_key(&pid) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Wait up to `timeout` for a process to exit. Returns whether it did.
+    async fn wait_for_exit(&self, pid: u32, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = match self.child_processes.lock().await.get_mut(&pid) {
+                Some(process) => match &mut process.child {
+                    Some(child) => child.try_wait()?,
+                    None => Some(ExitStatus::from_raw(0)),
+                },
+                // Already reaped
+                None => return Ok(true),
+            };
+            if let Some(status) = status {
+                self.child_processes.lock().await.remove(&pid);
+                self.compositor_app_handle
+                    .remove_application_position(pid)
+                    .await;
+                self.notify_exit(pid, status).await;
+                return Ok(true);
+            }
+
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep(STOP_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Start shutting down. Stops pending restarts and keeps the current processes from being restarted
+    /// once they exit.
+    pub async fn prepare_shutdown(&self) {
+        self.pending_restarts.lock().await.clear();
+        let pids: Vec<u32> = self.child_processes.lock().await.keys().copied().collect();
+        self.stopping.lock().await.extend(pids);
+    }
+
+    /// Reaps anything that exited and returns whether all processes are gone. Image tiles don't count since
+    /// there is no process to wait for.
+    pub async fn all_exited(&self) -> bool {
+        if let Err(error) = self.reap_exited().await {
+            println!("Failed to check on child processes: {error}");
+        }
+        self.child_processes
+            .lock()
+            .await
+            .values()
+            .all(|process| process.child.is_none())
+    }
+
+    /// Send a signal to every process
+    pub async fn signal_all(&self, signal: KillSignal) {
+        for (pid, process) in self.child_processes.lock().await.iter() {
+            if process.child.is_none() {
+                continue;
+            }
+            if let Err(error) = send_signal(*pid, signal) {
+                println!("Failed to send {signal:?} to {pid}: {error}");
+            }
+        }
+    }
+
+    /// SIGKILL every process that is left
+    pub async fn kill_all(&self) {
+        let pids: Vec<u32> = self.child_processes.lock().await.keys().copied().collect();
+        for pid in pids {
+            if let Err(error) = self.kill_process(pid).await {
+                println!("Failed to kill {pid}: {error}");
+            }
+        }
+    }
+
+    /// Last resort for when the compositor goes away without shutting down. SIGKILLs every process without
+    /// waiting on anything async, so it can be used from [Drop].
+    pub fn kill_all_now(&self) {
+        let Ok(mut child_processes) = self.child_processes.try_lock() else {
+            println!("Child processes are locked. Leaving them running.");
+            return;
+        };
+        for (pid, mut process) in child_processes.drain() {
+            println!("Killing {pid} ({})", process.config.executable);
+            if let Some(child) = &mut process.child {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+        if let Ok(cgroups) = self.cgroups.try_lock() {
+            for cgroup in cgroups.values() {
+                cgroup.remove();
+            }
+        }
+    }
+
+    /// Finds children that have exited on their own (crashed or closed), waits on them so they don't linger as
+    /// zombies and stops reserving their position on the canvas.
+    pub async fn reap_exited(&self) -> Result<Vec<(u32, ExitStatus)>> {
+        let mut exited = Vec::new();
+        let mut to_restart = Vec::new();
+        {
+            let mut child_processes = self.child_processes.lock().await;
+            for (pid, child_process) in child_processes.iter_mut() {
+                let Some(child) = &mut child_process.child else {
+                    continue;
+                };
+                if let Some(status) = child.try_wait()? {
+                    exited.push((*pid, status));
+                }
+            }
+            let stopping = self.stopping.lock().await;
+            for (pid, status) in &exited {
+                let process = child_processes.remove(pid).unwrap();
+                if !stopping.contains(pid) {
+                    to_restart.push((*pid, *status, process));
+                }
+            }
+        }
+
+        // Scheduling takes other locks, so it waits until the processes aren't held anymore
+        for (pid, status, process) in to_restart {
+            self.schedule_restart(pid, status, process).await;
+        }
+
+        for (pid, status) in &exited {
+            println!("Process {pid} exited with {status}");
+            self.compositor_app_handle
+                .remove_application_position(*pid)
+                .await;
+            self.notify_exit(*pid, *status).await;
+        }
+
+        Ok(exited)
+    }
+
+    /// Queue an exited process to be started again if its [crate::config::RestartConfig] asks for it
+    async fn schedule_restart(&self, pid: u32, status: ExitStatus, process: CompositorProcess) {
+        let restart = process.config.restart;
+        let should_restart = match restart.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !status.success(),
+            RestartPolicy::Always => true,
+        };
+        if !should_restart {
+            return;
+        }
+
+        // Staying up longer than the longest backoff means the app was healthy, so start counting again
+        let mut consecutive = process.restarts.consecutive;
+        if process.restarts.started.elapsed() >= Duration::from_millis(restart.max_backoff_ms) {
+            consecutive = 0;
+        }
+
+        if restart
+            .max_retries
+            .is_some_and(|max_retries| consecutive >= max_retries)
+        {
+            println!(
+                "Process {pid} ({}) restarted {consecutive} times in a row. Giving up.",
+                process.config.executable
+            );
+            return;
+        }
+
+        let backoff = restart.backoff(consecutive);
+        println!(
+            "Restarting {} (was pid {pid}) in {backoff:?}",
+            process.config.executable
+        );
+        let logs = self.logs.lock().await.get(&pid).cloned();
+        self.pending_restarts.lock().await.push(PendingRestart {
+            pid,
+            at: Instant::now() + backoff,
+            config: process.config,
+            restarts: RestartHistory {
+                total: process.restarts.total + 1,
+                consecutive: consecutive + 1,
+                started: Instant::now(),
+            },
+            logs,
+        });
+    }
+
+    /// Start any applications whose restart backoff has elapsed. They go back into the rect they had when
+    /// they exited.
+    pub async fn restart_due(&self) {
+        let due: Vec<PendingRestart> = {
+            let mut pending_restarts = self.pending_restarts.lock().await;
+            let now = Instant::now();
+            let (due, waiting) = pending_restarts
+                .drain(..)
+                .partition(|pending| pending.at <= now);
+            *pending_restarts = waiting;
+            due
+        };
+
+        for mut pending in due {
+            pending.restarts.started = Instant::now();
+            match self
+                .spawn_process_with_history(
+                    &pending.config,
+                    pending.restarts,
+                    pending.logs.clone(),
+                    false,
+                )
+                .await
+            {
+                Ok(pid) => {
+                    println!(
+                        "Restarted {} as pid {pid} (restart {})",
+                        pending.config.executable, pending.restarts.total
+                    );
+                    self.compositor_app_handle
+                        .retarget_mirrors(pending.pid, pid)
+                        .await;
+                    let _ = self.events.send(AppControllerEvent::Restarted {
+                        pid: pending.pid,
+                        new_pid: pid,
+                    });
+                }
+                Err(error) => {
+                    // Failing to even spawn counts as another failure, back off and try again
+                    println!("Failed to restart {}: {error}", pending.config.executable);
+                    let backoff = pending.config.restart.backoff(pending.restarts.consecutive);
+                    let out_of_retries = pending
+                        .config
+                        .restart
+                        .max_retries
+                        .is_some_and(|max_retries| pending.restarts.consecutive >= max_retries);
+                    if out_of_retries {
+                        self.compositor_app_handle
+                            .remove_mirrors_of(pending.pid)
+                            .await;
+                    } else {
+                        pending.at = Instant::now() + backoff;
+                        pending.restarts.total += 1;
+                        pending.restarts.consecutive += 1;
+                        self.pending_restarts.lock().await.push(pending);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Put back a process that didn't exit after SIGKILL so [Self::reap_exited] cleans up after it once it
+    /// does. It's marked as stopping so it isn't restarted.
+    async fn leave_to_reaper(&self, pid: u32, process: CompositorProcess) {
+        println!("Process {pid} is still there after SIGKILL. Leaving it to the reaper.");
+        self.stopping.lock().await.insert(pid);
+        self.child_processes.lock().await.insert(pid, process);
+    }
+
+    /// Cleans up what's left of an exited process and lets subscribers know it's gone
+    async fn notify_exit(&self, pid: u32, status: ExitStatus) {
+        // Mirrors stay around for an application that is coming back
+        let restarting = self
+            .pending_restarts
+            .lock()
+            .await
+            .iter()
+            .any(|pending| pending.pid == pid);
+        if !restarting {
+            self.compositor_app_handle.remove_mirrors_of(pid).await;
+        }
+
+        self.not_responding.lock().await.remove(&pid);
+        self.stopping.lock().await.remove(&pid);
+        if let Some(cgroup) = self.cgroups.lock().await.remove(&pid) {
+            cgroup.remove();
+        }
+
+        let mut exited_logs = self.exited_logs.lock().await;
+        exited_logs.push_back(pid);
+        if exited_logs.len() > EXITED_LOGS_KEPT {
+            let oldest = exited_logs.pop_front().unwrap();
+            self.logs.lock().await.remove(&oldest);
+        }
+
+        // Nobody listening is fine
+        let _ = self.events.send(AppControllerEvent::Exited {
+            pid,
+            code: status.code(),
+            signal: status.signal(),
+        });
+
+        // Fill the gap left by an automatically tiled application
+        self.rebalance().await;
+    }
+
     /// Lists the current processes managed by the compositor
     pub async fn list_processes(&self) -> Vec<(u32, AppConfig)> {
         self.child_processes
@@ -97,9 +1203,162 @@ impl AppController {
             .collect()
     }
 
+    /// Output captured from an application. Still available for a while after the application exited.
+    pub async fn logs(&self, pid: u32, tail: Option<usize>) -> Result<Vec<LogLine>> {
+        match self.logs.lock().await.get(&pid) {
+            Some(logs) => Ok(logs.tail(tail)),
+            None => Err(anyhow::anyhow!("No logs for pid {pid}")),
+        }
+    }
+
+    /// Resources currently used by an application. Includes everything it started if it has a cgroup.
+    pub async fn usage(&self, pid: u32) -> Result<ResourceUsage> {
+        match self.child_processes.lock().await.get(&pid) {
+            Some(process) if process.child.is_none() => {
+                return Err(anyhow!("{pid} is an image tile drawn by the compositor"))
+            }
+            Some(_) => {}
+            None => return Err(anyhow::anyhow!("Unknown pid {pid}")),
+        }
+
+        Ok(match self.cgroups.lock().await.get(&pid) {
+            Some(cgroup) => cgroup.usage(),
+            None => process_usage(pid),
+        })
+    }
+
+    /// Current processes that aren't answering pings
+    pub async fn list_not_responding(&self) -> Vec<u32> {
+        self.not_responding.lock().await.iter().copied().collect()
+    }
+
+    /// How many times each of the current processes has been restarted by the compositor
+    pub async fn list_restart_counts(&self) -> Vec<(u32, u32)> {
+        self.child_processes
+            .lock()
+            .await
+            .iter()
+            .map(|(pid, process)| (*pid, process.restarts.total))
+            .collect()
+    }
+
     /// Returns the dimensions of the overall compositor canvas
     pub async fn send_screen_size(screen_size: [u32; 2]) -> [u32; 2] {
         // TODO: Remove this? Store screen_size in app controller obj
         screen_size
     }
 }
+
+/// SIGKILL a process and wait up to [KILL_WAIT_TIMEOUT] on it. The process is polled for having exited so
+/// the runtime isn't blocked while it goes away. Returns `None` if it's still there after the timeout. Image
+/// tiles have nothing to stop and count as exiting successfully.
+async fn kill_and_wait(process: &mut CompositorProcess) -> std::io::Result<Option<ExitStatus>> {
+    let Some(child) = &mut process.child else {
+        return Ok(Some(ExitStatus::from_raw(0)));
+    };
+    child.kill()?;
+    let deadline = Instant::now() + KILL_WAIT_TIMEOUT;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(STOP_POLL_INTERVAL).await;
+    }
+}
+
+/// Whether a running application can be reused for `wanted` by moving it. Everything apart from where it's
+/// placed has to match. Applications with placeholders in their args or env were started for a specific
+/// area so that has to match as well.
+fn is_same_app(running: &AppConfig, wanted: &AppConfig) -> bool {
+    let uses_placeholders = wanted
+        .args
+        .iter()
+        .chain(wanted.env.values())
+        .any(|value| value.contains('{'));
+    if uses_placeholders
+        && (running.area != wanted.area
+            || running.border != wanted.border
+            || running.slot != wanted.slot)
+    {
+        return false;
+    }
+
+    let without_placement = |config: &AppConfig| {
+        let mut value = serde_json::to_value(config).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.remove("area");
+            object.remove("border");
+            object.remove("slot");
+        }
+        value
+    };
+    without_placement(running) == without_placement(wanted)
+}
+
+fn send_signal(pid: u32, signal: KillSignal) -> Result<()> {
+    let signal = match signal {
+        KillSignal::Term => libc::SIGTERM,
+        KillSignal::Int => libc::SIGINT,
+        KillSignal::Hup => libc::SIGHUP,
+        KillSignal::Quit => libc::SIGQUIT,
+        KillSignal::Usr1 => libc::SIGUSR1,
+        KillSignal::Usr2 => libc::SIGUSR2,
+        KillSignal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: kill has no memory safety preconditions. The pid is still ours since it hasn't been waited on.
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Periodically reaps children that exited on their own and restarts them according to their
+/// [crate::config::RestartConfig] until `cancel` fires
+pub fn start_child_reaper(app_controller: Arc<AppController>, mut cancel: broadcast::Receiver<()>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REAP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = cancel.recv() => return,
+                _ = interval.tick() => {}
+            }
+
+            if let Err(error) = app_controller.reap_exited().await {
+                println!("Failed to check on child processes: {error}");
+            }
+            app_controller.restart_due().await;
+        }
+    });
+}
+
+/// Switches to the attract layout (`true`) and back (`false`) as switches are sent, one at a time in the order
+/// they were sent. A switch waits for windows to open, so it can't be awaited from the frame loop that lets
+/// them open.
+pub fn start_idle_switcher(
+    app_controller: Arc<AppController>,
+    attract: Vec<AppConfig>,
+    mut cancel: broadcast::Receiver<()>,
+) -> mpsc::UnboundedSender<bool> {
+    let (switches, mut switch) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let idle = tokio::select! {
+                _ = cancel.recv() => return,
+                idle = switch.recv() => match idle {
+                    Some(idle) => idle,
+                    None => return,
+                },
+            };
+
+            if idle {
+                app_controller.enter_idle(&attract).await;
+            } else {
+                app_controller.leave_idle().await;
+            }
+        }
+    });
+    switches
+}
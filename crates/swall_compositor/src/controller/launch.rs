@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+
+use crate::config::{AppConfig, AppControllerEvent, ReadinessCheck};
+
+use super::AppController;
+
+/// How often [ReadinessCheck::Path] and [ReadinessCheck::Tcp] are checked
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `None` while an application is starting, then whether it became ready
+type Readiness = Option<bool>;
+
+/// Starts the applications from [crate::config::CompositorConfig::launch] in the background. Each one is
+/// started once everything in its [AppConfig::depends_on] is ready. Failures are reported as
+/// [AppControllerEvent::LaunchFailed] and only stop the applications that depend on the failed one.
+pub fn start_launch(
+    app_controller: Arc<AppController>,
+    launch: Vec<AppConfig>,
+    mut cancel: broadcast::Receiver<()>,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = cancel.recv() => {}
+            _ = launch_all(app_controller, launch) => {}
+        }
+    });
+}
+
+async fn launch_all(app_controller: Arc<AppController>, launch: Vec<AppConfig>) {
+    let problems = dependency_problems(&launch);
+
+    let mut notifiers = HashMap::new();
+    let mut readiness = HashMap::new();
+    for config in &launch {
+        if let Some(name) = &config.name {
+            if !readiness.contains_key(name) {
+                let (notifier, ready) = watch::channel::<Readiness>(None);
+                notifiers.insert(name.clone(), notifier);
+                readiness.insert(name.clone(), ready);
+            }
+        }
+    }
+
+    // Every application gets its own task so independent ones start in parallel. That also means apps
+    // without dependencies start in no particular order. Use depends_on or start_delay_ms to order them.
+    let mut tasks = JoinSet::new();
+    for (index, config) in launch.into_iter().enumerate() {
+        let dependencies: Vec<_> = config
+            .depends_on
+            .iter()
+            .filter_map(|dependency| Some((dependency.clone(), readiness.get(dependency)?.clone())))
+            .collect();
+        let notifier = match problems.get(&index) {
+            // A duplicate name must not report readiness for the application that owns the name
+            Some(_) => None,
+            None => config.name.as_ref().and_then(|name| notifiers.remove(name)),
+        };
+
+        tasks.spawn(launch_one(
+            app_controller.clone(),
+            config,
+            dependencies,
+            notifier,
+            problems.get(&index).cloned(),
+        ));
+    }
+
+    while tasks.join_next().await.is_some() {}
+}
+
+async fn launch_one(
+    app_controller: Arc<AppController>,
+    config: AppConfig,
+    dependencies: Vec<(String, watch::Receiver<Readiness>)>,
+    notifier: Option<watch::Sender<Readiness>>,
+    problem: Option<String>,
+) {
+    let result = async {
+        if let Some(problem) = problem {
+            bail!(problem);
+        }
+
+        for (dependency, mut ready) in dependencies {
+            let became_ready = ready
+                .wait_for(Option::is_some)
+                .await
+                .map(|ready| *ready == Some(true))
+                .unwrap_or(false);
+            if !became_ready {
+                bail!("dependency '{dependency}' failed to start");
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(config.start_delay_ms)).await;
+
+        // Subscribe before spawning so the window can't be mapped before we are listening
+        let mut events = app_controller.subscribe();
+        let pid = app_controller.spawn_process(&config).await?;
+
+        let ready_timeout = Duration::from_millis(config.ready_timeout_ms);
+        tokio::time::timeout(
+            ready_timeout,
+            wait_until_ready(&config.ready, pid, &mut events),
+        )
+        .await
+        .map_err(|_| anyhow!("not ready after {ready_timeout:?}"))?
+    }
+    .await;
+
+    if let Some(notifier) = notifier {
+        let _ = notifier.send(Some(result.is_ok()));
+    }
+
+    if let Err(error) = result {
+        let name = config.name.unwrap_or(config.executable);
+        app_controller.report_launch_failure(name, error.to_string());
+    }
+}
+
+async fn wait_until_ready(
+    check: &ReadinessCheck,
+    pid: u32,
+    events: &mut broadcast::Receiver<AppControllerEvent>,
+) -> Result<()> {
+    match check {
+        ReadinessCheck::Spawned => Ok(()),
+        ReadinessCheck::Window => loop {
+            match events.recv().await {
+                Ok(AppControllerEvent::WindowMapped { pid: mapped }) if mapped == pid => {
+                    return Ok(())
+                }
+                Ok(AppControllerEvent::Exited {
+                    pid: exited,
+                    code,
+                    signal,
+                }) if exited == pid => {
+                    bail!("exited before opening a window (code: {code:?}, signal: {signal:?})")
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => bail!("app controller stopped"),
+            }
+        },
+        ReadinessCheck::Path { path } => {
+            while !tokio::fs::try_exists(path).await.unwrap_or(false) {
+                tokio::time::sleep(READY_POLL_INTERVAL).await;
+            }
+            Ok(())
+        }
+        ReadinessCheck::Tcp { address } => {
+            while TcpStream::connect(address).await.is_err() {
+                tokio::time::sleep(READY_POLL_INTERVAL).await;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Finds applications that can never start because they reuse a name, depend on an unknown name or are
+/// part of a dependency cycle. Keyed by the index of the application in the launch list.
+fn dependency_problems(launch: &[AppConfig]) -> HashMap<usize, String> {
+    // The first application with a name owns it
+    let mut by_name: HashMap<&str, usize> = HashMap::new();
+    for (index, config) in launch.iter().enumerate() {
+        if let Some(name) = &config.name {
+            by_name.entry(name).or_insert(index);
+        }
+    }
+
+    let mut problems = HashMap::new();
+    for (index, config) in launch.iter().enumerate() {
+        if let Some(name) = &config.name {
+            if by_name[name.as_str()] != index {
+                problems.insert(index, "another application has the same name".into());
+                continue;
+            }
+        }
+
+        if let Some(unknown) = config
+            .depends_on
+            .iter()
+            .find(|dependency| !by_name.contains_key(dependency.as_str()))
+        {
+            problems.insert(index, format!("depends on unknown application '{unknown}'"));
+            continue;
+        }
+
+        // Walk the dependencies to see if they lead back here
+        let mut stack: Vec<usize> = vec![index];
+        let mut seen = vec![false; launch.len()];
+        while let Some(current) = stack.pop() {
+            for dependency in &launch[current].depends_on {
+                let Some(&next) = by_name.get(dependency.as_str()) else {
+                    continue;
+                };
+                if next == index {
+                    problems.insert(index, "is part of a dependency cycle".into());
+                    stack.clear();
+                    break;
+                }
+                if !seen[next] {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+    }
+
+    problems
+}
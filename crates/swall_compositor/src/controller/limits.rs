@@ -0,0 +1,175 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Result;
+
+use crate::config::{ResourceLimits, ResourceUsage};
+
+/// Controllers the app cgroups need from the delegated root
+const CGROUP_CONTROLLERS: &str = "+memory +cpu +pids";
+
+/// Enable the controllers needed for [ResourceLimits] in a delegated cgroup v2 subtree. Fails if the
+/// compositor was not given write access to it.
+pub fn prepare_cgroup_root(root: &Path) -> io::Result<()> {
+    fs::write(root.join("cgroup.subtree_control"), CGROUP_CONTROLLERS)
+}
+
+/// A cgroup made for a single application inside of the delegated subtree
+#[derive(Debug, Clone)]
+pub struct AppCgroup {
+    path: PathBuf,
+}
+
+impl AppCgroup {
+    /// Creates the cgroup and writes the limits into it. The application is moved into it when it starts.
+    pub fn create(root: &Path, name: &str, limits: &ResourceLimits) -> Result<Self> {
+        let path = root.join(name);
+        fs::create_dir(&path)?;
+        let cgroup = Self { path };
+
+        let write_limit = |file: &str, value: Option<u64>| -> io::Result<()> {
+            match value {
+                Some(value) => fs::write(cgroup.path.join(file), value.to_string()),
+                None => Ok(()),
+            }
+        };
+        let result = write_limit("memory.max", limits.memory_max)
+            .and_then(|_| write_limit("cpu.weight", limits.cpu_weight))
+            .and_then(|_| write_limit("pids.max", limits.pids_max));
+        if let Err(error) = result {
+            cgroup.remove();
+            return Err(error.into());
+        }
+
+        Ok(cgroup)
+    }
+
+    /// Move a process into the cgroup. Does nothing if it is already in it.
+    pub fn add_process(&self, pid: u32) -> io::Result<()> {
+        fs::write(self.path.join("cgroup.procs"), pid.to_string())
+    }
+
+    /// Kill anything left in the cgroup (e.g. orphaned grandchildren) and remove it
+    pub fn remove(&self) {
+        // `cgroup.kill` needs linux 5.14. Older kernels just leave the stragglers running.
+        let _ = fs::write(self.path.join("cgroup.kill"), "1");
+        if let Err(error) = fs::remove_dir(&self.path) {
+            println!("Failed to remove cgroup '{}': {error}", self.path.display());
+        }
+    }
+
+    pub fn usage(&self) -> ResourceUsage {
+        let read = |file: &str| fs::read_to_string(self.path.join(file)).ok();
+        ResourceUsage {
+            memory_bytes: read("memory.current").and_then(|value| value.trim().parse().ok()),
+            cpu_usage_usec: read("cpu.stat").and_then(|stat| {
+                stat.lines()
+                    .find_map(|line| line.strip_prefix("usage_usec "))
+                    .and_then(|value| value.trim().parse().ok())
+            }),
+            pids: read("pids.current").and_then(|value| value.trim().parse().ok()),
+        }
+    }
+}
+
+/// Apply the limits to a process that's about to be spawned. With a cgroup the process is moved into it
+/// before it executes anything. Without one only [ResourceLimits::memory_max] (as `RLIMIT_AS`) and
+/// [ResourceLimits::nice] can be enforced.
+///
+/// [Command::pre_exec] runs after [Command::uid], so these are applied as [crate::config::AppConfig::user].
+/// Anything that user can't do on its own, like raising its priority with a negative nice, fails the spawn.
+pub fn apply_limits(
+    command: &mut Command,
+    limits: &ResourceLimits,
+    cgroup: Option<&AppCgroup>,
+) -> Result<()> {
+    // Allocating isn't allowed after fork so everything is prepared up front
+    let cgroup_procs = cgroup
+        .map(|cgroup| CString::new(cgroup.path.join("cgroup.procs").as_os_str().as_bytes()))
+        .transpose()?;
+    let address_space = match cgroup {
+        Some(_) => None,
+        None => limits.memory_max,
+    };
+    let nice = limits.nice;
+
+    // SAFETY: Only async-signal-safe syscalls are made in the closure
+    unsafe {
+        command.pre_exec(move || {
+            // This runs after switching to [crate::config::AppConfig::user] which might not be allowed to
+            // write here. In that case the parent moves the process with [AppCgroup::add_process] instead.
+            if let Some(cgroup_procs) = &cgroup_procs {
+                let fd = libc::open(cgroup_procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd >= 0 {
+                    // Writing 0 moves the writing process
+                    libc::write(fd, b"0".as_ptr().cast(), 1);
+                    libc::close(fd);
+                }
+            }
+
+            if let Some(bytes) = address_space {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes as libc::rlim_t,
+                    rlim_max: bytes as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+/// Usage of a process without a cgroup, read from `/proc`. Only covers the process itself, not its
+/// children.
+pub fn process_usage(pid: u32) -> ResourceUsage {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok();
+    let memory_bytes = status.as_deref().and_then(|status| {
+        let kilobytes: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kilobytes * 1024)
+    });
+
+    let cpu_usage_usec = fs::read_to_string(format!("/proc/{pid}/stat"))
+        .ok()
+        .and_then(|stat| {
+            // The command name can contain spaces, so start after its closing bracket. utime and stime are
+            // the 14th and 15th fields, which are the 12th and 13th after the command name.
+            let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+            let utime: u64 = fields.next()?.parse().ok()?;
+            let stime: u64 = fields.next()?.parse().ok()?;
+            // SAFETY: sysconf has no preconditions
+            let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+            if ticks_per_second <= 0 {
+                return None;
+            }
+            Some((utime + stime) * 1_000_000 / ticks_per_second as u64)
+        });
+
+    ResourceUsage {
+        memory_bytes,
+        cpu_usage_usec,
+        pids: None,
+    }
+}
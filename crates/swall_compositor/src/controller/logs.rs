@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStderr, ChildStdout};
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::broadcast;
+
+use crate::config::{AppControllerEvent, LogConfig, LogLine, LogStream};
+
+/// Output captured from an application. Holds the last [LogConfig::buffer_lines] lines in memory and
+/// optionally copies everything into a rotating log file.
+///
+/// Shared between restarts of the same application so the output leading up to a crash isn't lost.
+#[derive(Debug)]
+pub struct AppLogs {
+    config: LogConfig,
+    inner: Mutex<AppLogsInner>,
+}
+
+#[derive(Debug)]
+struct AppLogsInner {
+    lines: VecDeque<LogLine>,
+    file: Option<File>,
+    file_size: u64,
+}
+
+impl AppLogs {
+    pub fn new(config: &LogConfig) -> io::Result<Self> {
+        let (file, file_size) = match &config.file {
+            Some(path) => {
+                let file = open_log_file(path)?;
+                let size = file.metadata()?.len();
+                (Some(file), size)
+            }
+            None => (None, 0),
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            inner: Mutex::new(AppLogsInner {
+                lines: VecDeque::with_capacity(config.buffer_lines),
+                file,
+                file_size,
+            }),
+        })
+    }
+
+    /// The last `tail` lines (or everything that's buffered)
+    pub fn tail(&self, tail: Option<usize>) -> Vec<LogLine> {
+        let inner = self.inner.lock().unwrap();
+        let skip = tail.map_or(0, |tail| inner.lines.len().saturating_sub(tail));
+        inner.lines.iter().skip(skip).cloned().collect()
+    }
+
+    fn push(&self, line: LogLine) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(path) = &self.config.file {
+            if let Err(error) = inner.write_to_file(path, &self.config, &line) {
+                println!("Failed to write to log file '{}': {error}", path.display());
+            }
+        }
+
+        if self.config.buffer_lines == 0 {
+            return;
+        }
+        if inner.lines.len() == self.config.buffer_lines {
+            inner.lines.pop_front();
+        }
+        inner.lines.push_back(line);
+    }
+}
+
+impl AppLogsInner {
+    fn write_to_file(&mut self, path: &Path, config: &LogConfig, line: &LogLine) -> io::Result<()> {
+        if self.file_size >= config.max_file_bytes {
+            // Close the current file before it gets moved out of the way
+            self.file = None;
+            rotate_log_files(path, config.max_files)?;
+            self.file = Some(open_log_file(path)?);
+            self.file_size = 0;
+        }
+
+        if let Some(file) = &mut self.file {
+            let stream = match line.stream {
+                LogStream::Stdout => "stdout",
+                LogStream::Stderr => "stderr",
+            };
+            let formatted = format!("[{stream}] {}\n", line.line);
+            file.write_all(formatted.as_bytes())?;
+            self.file_size += formatted.len() as u64;
+        }
+
+        Ok(())
+    }
+}
+
+fn open_log_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Shifts `app.log` to `app.log.1`, `app.log.1` to `app.log.2` and so on, dropping the oldest once there
+/// are `max_files` of them.
+fn rotate_log_files(path: &Path, max_files: u32) -> io::Result<()> {
+    let numbered = |n: u32| -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        name.into()
+    };
+
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+
+    for n in (1..max_files).rev() {
+        match fs::rename(numbered(n), numbered(n + 1)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+    }
+    fs::rename(path, numbered(1))
+}
+
+/// Read the output of a child until it closes its end of the pipes
+pub fn capture_output(
+    pid: u32,
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+    logs: Arc<AppLogs>,
+    events: broadcast::Sender<AppControllerEvent>,
+) {
+    if let Some(stdout) = stdout.and_then(|out| tokio::process::ChildStdout::from_std(out).ok()) {
+        spawn_reader(pid, LogStream::Stdout, stdout, logs.clone(), events.clone());
+    }
+    if let Some(stderr) = stderr.and_then(|err| tokio::process::ChildStderr::from_std(err).ok()) {
+        spawn_reader(pid, LogStream::Stderr, stderr, logs, events);
+    }
+}
+
+fn spawn_reader(
+    pid: u32,
+    stream: LogStream,
+    reader: impl AsyncRead + Unpin + Send + 'static,
+    logs: Arc<AppLogs>,
+    events: broadcast::Sender<AppControllerEvent>,
+) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return,
+                Err(error) => {
+                    println!("Stopped reading {stream:?} of {pid}: {error}");
+                    return;
+                }
+            };
+
+            let line = LogLine { stream, line };
+            logs.push(line.clone());
+
+            // Nobody following is fine
+            let _ = events.send(AppControllerEvent::Log { pid, line });
+        }
+    });
+}
@@ -1,5 +1,15 @@
+mod access;
+mod command;
 mod controller;
+mod launch;
+mod limits;
+mod logs;
+mod policy;
+mod schedule;
 mod socket;
+mod tiling;
 
-pub use controller::AppController;
+pub use controller::{start_child_reaper, start_idle_switcher, AppController};
+pub use launch::start_launch;
+pub use schedule::{scheduled_entry, start_schedule};
 pub use socket::start_controller_socket;
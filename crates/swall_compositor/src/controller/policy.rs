@@ -0,0 +1,129 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+
+use crate::config::{AppConfig, SpawnPolicy};
+
+/// Checks whether the control socket may spawn `app_config`
+pub fn check_spawn(policy: &SpawnPolicy, app_config: &AppConfig) -> Result<()> {
+    if policy.named_only {
+        return Err(anyhow!(
+            "Spawn policy only allows named apps. Use SpawnApp with one of: {}",
+            policy.apps.keys().cloned().collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    let Some(allowed_executables) = &policy.allowed_executables else {
+        return Ok(());
+    };
+
+    if !allowed_executables.contains(&app_config.executable) {
+        return Err(anyhow!(
+            "Executable '{}' is not in the spawn policy's allowed executables",
+            app_config.executable
+        ));
+    }
+
+    let set = restricted_fields_set(app_config)?;
+    if !set.is_empty() {
+        return Err(anyhow!(
+            "{} can't be set through Spawn when executables are restricted. Use a named app instead",
+            set.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// The fields of [AppConfig] a Spawn may set when executables are restricted. Anything else could be used to
+/// run something other than the allowed executable (e.g. `LD_PRELOAD` in `env`), to gain privileges or to
+/// have the compositor read or write files of its choosing (e.g. `log.file` or `image`), so it's only allowed
+/// in the trusted named app definitions.
+const RESTRICTED_SPAWN_FIELDS: &[&str] = &[
+    "name",
+    "executable",
+    "args",
+    "area",
+    "slot",
+    "border",
+    "restart",
+    "unresponsive",
+];
+
+/// Fields of `app_config` outside of [RESTRICTED_SPAWN_FIELDS] that aren't left at their defaults
+fn restricted_fields_set(app_config: &AppConfig) -> Result<Vec<String>> {
+    let Value::Object(requested) = serde_json::to_value(app_config)? else {
+        return Err(anyhow!("App config is not an object"));
+    };
+    let allowed: Map<String, Value> = requested
+        .iter()
+        .filter(|(field, _)| RESTRICTED_SPAWN_FIELDS.contains(&field.as_str()))
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect();
+    // Everything that isn't allowed is filled in with its default
+    let defaults: AppConfig = serde_json::from_value(Value::Object(allowed))?;
+    let Value::Object(defaults) = serde_json::to_value(defaults)? else {
+        return Err(anyhow!("App config is not an object"));
+    };
+
+    Ok(requested
+        .iter()
+        .filter(|(field, value)| defaults.get(*field) != Some(*value))
+        .map(|(field, _)| field.clone())
+        .collect())
+}
+
+/// Appends a spawn attempt to [SpawnPolicy::audit_log] as a line of JSON. `name` is the requested named app
+/// and `app_config` is what was going to be spawned, if known. `denied` is whether the policy rejected it, as
+/// opposed to the spawn itself failing.
+pub fn audit_spawn(
+    policy: &SpawnPolicy,
+    name: Option<&str>,
+    app_config: Option<&AppConfig>,
+    result: Result<u32, &anyhow::Error>,
+    denied: bool,
+) {
+    let (outcome, pid, error) = match result {
+        Ok(pid) => ("spawned", Some(pid), None),
+        Err(error) if denied => ("denied", None, Some(error.to_string())),
+        Err(error) => ("failed", None, Some(error.to_string())),
+    };
+    let name = name.or_else(|| app_config?.name.as_deref());
+    let executable = app_config.map(|app_config| app_config.executable.as_str());
+    println!(
+        "Spawn request for '{}': {outcome}",
+        executable.or(name).unwrap_or_default()
+    );
+
+    let Some(audit_log) = &policy.audit_log else {
+        return;
+    };
+
+    let entry = json!({
+        "time": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default(),
+        "name": name,
+        "executable": executable,
+        "args": app_config.map(|app_config| &app_config.args),
+        "outcome": outcome,
+        "pid": pid,
+        "error": error,
+    });
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_log)
+        .and_then(|mut file| writeln!(file, "{entry}"));
+    if let Err(error) = written {
+        println!(
+            "Failed to write to audit log '{}': {error}",
+            audit_log.display()
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+use crate::config::{AppConfig, ScheduleEntry, TimeOfDay, Weekday};
+
+use super::AppController;
+
+/// How often the schedule is checked. Layouts change within this long of an entry's boundary.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Switches between [crate::config::CompositorConfig::layouts] as the entries of the schedule start. The
+/// layout that should be showing right now is applied straight away, so restarting the compositor in the
+/// middle of an entry catches up. A layout is only applied when an entry starts, so a layout applied over the
+/// control socket stays until the next entry does.
+pub fn start_schedule(
+    app_controller: Arc<AppController>,
+    layouts: BTreeMap<String, Vec<AppConfig>>,
+    schedule: Vec<ScheduleEntry>,
+    mut cancel: broadcast::Receiver<()>,
+) {
+    if schedule.is_empty() {
+        return;
+    }
+    for entry in &schedule {
+        if !layouts.contains_key(&entry.layout) {
+            println!(
+                "Schedule refers to unknown layout '{}'. It will be skipped.",
+                entry.layout
+            );
+        }
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULE_CHECK_INTERVAL);
+        // Index of the entry that was active on the last check
+        let mut current: Option<usize> = None;
+        loop {
+            tokio::select! {
+                _ = cancel.recv() => return,
+                _ = interval.tick() => {}
+            }
+
+            let active = active_entry(&schedule);
+            if active == current {
+                continue;
+            }
+            current = active;
+            let Some(entry) = active.map(|index| &schedule[index]) else {
+                continue;
+            };
+            let Some(apps) = layouts.get(&entry.layout) else {
+                continue;
+            };
+
+            println!("Schedule switching to layout '{}'", entry.layout);
+            app_controller.set_interactive_layout(apps.clone()).await;
+        }
+    });
+}
+
+/// The first entry of the schedule that is active right now
+pub fn scheduled_entry(schedule: &[ScheduleEntry]) -> Option<&ScheduleEntry> {
+    active_entry(schedule).map(|index| &schedule[index])
+}
+
+/// Index of [scheduled_entry]
+fn active_entry(schedule: &[ScheduleEntry]) -> Option<usize> {
+    let (day, time) = local_now();
+    schedule.iter().position(|entry| entry.is_active(day, time))
+}
+
+/// Day of the week and time of day in the local time zone
+fn local_now() -> (Weekday, TimeOfDay) {
+    // SAFETY: localtime_r only writes to the tm it's given. A zeroed tm is a valid value.
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    (
+        Weekday::from_days_from_sunday(tm.tm_wday as u32),
+        TimeOfDay((tm.tm_hour * 60 + tm.tm_min) as u32),
+    )
+}
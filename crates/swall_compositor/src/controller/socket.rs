@@ -1,4 +1,6 @@
-use std::{path::Path, sync::Arc};
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::{collections::HashSet, path::Path, sync::Arc};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -15,22 +17,41 @@ use tokio_stream::{
     StreamExt,
 };
 
-use crate::config::{AppConfig, AppControllerCommand};
+use crate::config::{
+    AppConfig, AppControllerCommand, AppControllerEvent, ControllerAccess, ControllerRole,
+    KillSignal, LogLine, ResourceUsage,
+};
 
+use super::access::client_role;
 use super::AppController;
 
 pub const MSG_SPLITTER: u8 = 0x1e;
 
+/// Where the app controller listens for commands
+pub const CONTROLLER_SOCKET: &str = "/tmp/swall/control-0";
+
 /// Starts the app controller for the swall compositor
 /// Implements a receive -> process -> respond loop to process data
 /// Data is modified through the shared state struct
+/// Connecting clients are given a [ControllerRole] based on `access`
 pub async fn start_controller_socket(
     inner_state: Arc<AppController>,
     screen_size: [u32; 2],
+    access: ControllerAccess,
 ) -> Result<broadcast::Sender<()>> {
     // Create the socket in which the controller will receive commands over
-    let app_controller_socket: &Path = Path::new("/tmp/swall/control-0");
-    let control_listener = UnixListener::bind(app_controller_socket.to_path_buf())?;
+    let app_controller_socket: &Path = Path::new(CONTROLLER_SOCKET);
+    // Bound with no access for anyone else so nobody can connect before the mode is set. The umask is
+    // process wide, anything created meanwhile only ends up stricter.
+    // SAFETY: umask can't fail
+    let umask = unsafe { libc::umask(0o177) };
+    let control_listener = UnixListener::bind(app_controller_socket.to_path_buf());
+    unsafe { libc::umask(umask) };
+    let control_listener = control_listener?;
+    fs::set_permissions(
+        app_controller_socket,
+        Permissions::from_mode(access.socket_mode),
+    )?;
     println!("Controller Socket Created");
 
     // TODO: Use tokio watch instead of broadcast here?
@@ -54,15 +75,34 @@ pub async fn start_controller_socket(
 
         while let Some(control_event) = control_stream.next().await {
             match control_event {
-                ControlEvent::NewStream(Ok(new_stream)) => {
+                ControlEvent::NewStream(Ok(mut new_stream)) => {
                     let inner_state = inner_state.clone();
                     let controller_cancel = controller_cancel.resubscribe();
+                    let access = access.clone();
                     spawn(async move {
+                        let role = match client_role(&new_stream, &access) {
+                            Ok(role) => role,
+                            Err(error) => {
+                                println!("Rejected controller connection: {}", error);
+                                let response = json!(AppControllerResponse {
+                                    success: false,
+                                    error: format!("{}", error).into(),
+                                    ..Default::default()
+                                })
+                                .to_string();
+                                let _ = new_stream.write_all(response.as_bytes()).await;
+                                let _ = new_stream.write_all(&[MSG_SPLITTER]).await;
+                                return;
+                            }
+                        };
+                        println!("Controller connected as {:?}", role);
+
                         let (stream_reader, mut stream_writer) = new_stream.into_split();
                         let stream_reader = BufReader::new(stream_reader);
 
                         enum ControlEvent {
                             NewSegment(Result<Vec<u8>, std::io::Error>),
+                            AppEvent(AppControllerEvent),
                             Cancel,
                         }
                         let mut segments = SplitStream::new(stream_reader.split(MSG_SPLITTER))
@@ -70,21 +110,73 @@ pub async fn start_controller_socket(
                             .merge(
                                 BroadcastStream::new(controller_cancel)
                                     .map(|_| ControlEvent::Cancel),
+                            )
+                            .merge(
+                                // Events missed because this connection fell behind are dropped
+                                BroadcastStream::new(inner_state.subscribe())
+                                    .filter_map(Result::ok)
+                                    .map(ControlEvent::AppEvent),
                             );
 
+                        // Events are only forwarded once the controller asks for them
+                        let mut subscribed = false;
+                        let mut followed_logs = HashSet::new();
+
                         while let Some(control_event) = segments.next().await {
                             match control_event {
                                 ControlEvent::NewSegment(segment) => {
                                     let segment = segment.unwrap();
                                     let message = std::str::from_utf8(&segment).unwrap();
                                     println!("Read from controller: {}", message);
+                                    let command: serde_json::Result<AppControllerCommand> =
+                                        serde_json::from_str(message);
+                                    if let Ok(AppControllerCommand::Subscribe) = command {
+                                        subscribed = true;
+                                    }
                                     let response =
-                                        process_command(&message, &inner_state, screen_size).await;
+                                        process_command(&message, &inner_state, screen_size, role)
+                                            .await;
+                                    // Only follow apps the controller was allowed to see the logs of
+                                    if let Ok(AppControllerCommand::Logs {
+                                        pid,
+                                        follow: true,
+                                        ..
+                                    }) = command
+                                    {
+                                        let accepted =
+                                            serde_json::from_str::<AppControllerResponse>(
+                                                &response,
+                                            )
+                                            .is_ok_and(|response| response.success);
+                                        if accepted {
+                                            followed_logs.insert(pid);
+                                        }
+                                    }
 
                                     stream_writer.write_all(response.as_bytes()).await.unwrap();
                                     stream_writer.write_all(&[MSG_SPLITTER]).await.unwrap();
                                     println!("Response sent {}", response);
                                 }
+                                ControlEvent::AppEvent(event) => {
+                                    // A restarted app keeps being followed under its new pid
+                                    if let AppControllerEvent::Restarted { pid, new_pid } = &event {
+                                        if followed_logs.remove(pid) {
+                                            followed_logs.insert(*new_pid);
+                                        }
+                                    }
+                                    // Log lines only go to connections following that app
+                                    let forward = match &event {
+                                        AppControllerEvent::Log { pid, .. } => {
+                                            followed_logs.contains(pid)
+                                        }
+                                        _ => subscribed,
+                                    };
+                                    if forward {
+                                        let message = json!(event).to_string();
+                                        stream_writer.write_all(message.as_bytes()).await.unwrap();
+                                        stream_writer.write_all(&[MSG_SPLITTER]).await.unwrap();
+                                    }
+                                }
                                 ControlEvent::Cancel => return,
                             }
                         }
@@ -102,93 +194,200 @@ pub async fn start_controller_socket(
 }
 
 /// Processes a command string (JSON) of format [CompositorAction] and takes appropriate action
+/// Commands that need a higher role than `role` are refused
 pub(crate) async fn process_command(
     command: &str,
-    app_controller: impl AsRef<AppController>,
+    app_controller: &Arc<AppController>,
     screen_size: [u32; 2],
+    role: ControllerRole,
 ) -> String {
     let compositor_action: AppControllerCommand = match serde_json::from_str(command) {
         Ok(result) => result,
         Err(error) => {
             return json!(AppControllerResponse {
                 success: false,
-                pid: None,
-                screen_width: None,
-                screen_height: None,
-                config: None,
-                process_ids: None,
                 error: format!("{}", error).into(),
+                ..Default::default()
             })
             .to_string()
         }
     };
 
-    let app_controller = app_controller.as_ref();
+    let required_role = compositor_action.required_role();
+    if role < required_role {
+        return json!(AppControllerResponse {
+            success: false,
+            error: format!(
+                "Permission denied: needs the {:?} role but the connection has {:?}",
+                required_role, role
+            )
+            .into(),
+            ..Default::default()
+        })
+        .to_string();
+    }
+
     let res = match compositor_action {
         AppControllerCommand::Spawn { config } => {
-            app_controller.spawn_process(&config).await.map(|pid| {
+            app_controller.spawn_requested(&config).await.map(|pid| {
                 json!(AppControllerResponse {
                     success: true,
                     pid: Some(pid),
-                    screen_width: None,
-                    screen_height: None,
                     config: Some(config),
-                    process_ids: None,
-                    error: None
+                    ..Default::default()
                 })
                 .to_string()
             })
         }
+        AppControllerCommand::SpawnApp { name, area } => app_controller
+            .spawn_named(&name, area)
+            .await
+            .map(|(pid, config)| {
+                json!(AppControllerResponse {
+                    success: true,
+                    pid: Some(pid),
+                    config: Some(config),
+                    ..Default::default()
+                })
+                .to_string()
+            }),
         AppControllerCommand::Move { pid, rect } => {
             app_controller.resize_process(&pid, &rect).await.map(|pid| {
                 json!(AppControllerResponse {
                     success: true,
                     pid: Some(pid),
-                    screen_width: None,
-                    screen_height: None,
-                    config: None,
-                    process_ids: None,
-                    error: None
+                    ..Default::default()
                 })
                 .to_string()
             })
         }
-        AppControllerCommand::Kill { pid } => app_controller.kill_process(pid).await.map(|_| {
+        AppControllerCommand::Kill {
+            pid,
+            graceful,
+            signal,
+        } => match (graceful, signal) {
+            (false, None) => app_controller.kill_process(pid).await,
+            (graceful, signal) => {
+                app_controller
+                    .stop_process(pid, graceful, signal.unwrap_or(KillSignal::Term))
+                    .await
+            }
+        }
+        .map(|_| {
             json!(AppControllerResponse {
                 success: true,
                 pid: Some(pid),
-                screen_width: None,
-                screen_height: None,
-                config: None,
-                process_ids: None,
-                error: None
+                ..Default::default()
             })
             .to_string()
         }),
         AppControllerCommand::List => {
             let configs = app_controller.list_processes().await;
+            let restart_counts = app_controller.list_restart_counts().await;
+            let not_responding = app_controller.list_not_responding().await;
 
             Ok(json!(AppControllerResponse {
                 success: true,
-                pid: None,
-                screen_width: None,
-                screen_height: None,
-                config: None,
                 process_ids: Some(configs),
-                error: None
+                restart_counts: Some(restart_counts),
+                not_responding: Some(not_responding),
+                ..Default::default()
+            })
+            .to_string())
+        }
+        AppControllerCommand::Subscribe => Ok(json!(AppControllerResponse {
+            success: true,
+            ..Default::default()
+        })
+        .to_string()),
+        AppControllerCommand::Logs { pid, tail, .. } => {
+            app_controller.logs(pid, tail).await.map(|logs| {
+                json!(AppControllerResponse {
+                    success: true,
+                    pid: Some(pid),
+                    logs: Some(logs),
+                    ..Default::default()
+                })
+                .to_string()
+            })
+        }
+        AppControllerCommand::Usage { pid } => app_controller.usage(pid).await.map(|usage| {
+            json!(AppControllerResponse {
+                success: true,
+                pid: Some(pid),
+                usage: Some(usage),
+                ..Default::default()
+            })
+            .to_string()
+        }),
+        AppControllerCommand::ApplyLayout { name } => {
+            match app_controller.apply_named_layout(&name).await {
+                Ok(()) => {
+                    let configs = app_controller.list_processes().await;
+                    Ok(json!(AppControllerResponse {
+                        success: true,
+                        process_ids: Some(configs),
+                        ..Default::default()
+                    })
+                    .to_string())
+                }
+                Err(error) => Err(error),
+            }
+        }
+        AppControllerCommand::SetOverlay { widget } => {
+            app_controller.set_overlay(widget).await;
+            Ok(json!(AppControllerResponse {
+                success: true,
+                ..Default::default()
+            })
+            .to_string())
+        }
+        AppControllerCommand::SetBackground { background } => {
+            app_controller.set_background(background).await;
+            Ok(json!(AppControllerResponse {
+                success: true,
+                ..Default::default()
             })
             .to_string())
         }
+        AppControllerCommand::ClearOverlay { id } => {
+            app_controller.clear_overlay(id.as_deref()).await.map(|_| {
+                json!(AppControllerResponse {
+                    success: true,
+                    ..Default::default()
+                })
+                .to_string()
+            })
+        }
+        AppControllerCommand::Mirror {
+            source_pid,
+            rect,
+            input,
+        } => app_controller
+            .mirror(source_pid, rect, input)
+            .await
+            .map(|id| {
+                json!(AppControllerResponse {
+                    success: true,
+                    pid: Some(id),
+                    ..Default::default()
+                })
+                .to_string()
+            }),
+        AppControllerCommand::Unmirror { id } => app_controller.unmirror(id).await.map(|_| {
+            json!(AppControllerResponse {
+                success: true,
+                ..Default::default()
+            })
+            .to_string()
+        }),
         AppControllerCommand::ScreenSize => {
             let screen_size = AppController::send_screen_size(screen_size).await;
             Ok(json!(AppControllerResponse {
                 success: true,
-                pid: None,
                 screen_width: Some(screen_size[0]),
                 screen_height: Some(screen_size[1]),
-                config: None,
-                process_ids: None,
-                error: None
+                ..Default::default()
             })
             .to_string())
         }
@@ -198,12 +397,8 @@ pub(crate) async fn process_command(
         Ok(msg) => msg,
         Err(error) => json!(AppControllerResponse {
             success: false,
-            pid: None,
-            screen_width: None,
-            screen_height: None,
-            config: None,
-            process_ids: None,
             error: format!("{}", error).into(),
+            ..Default::default()
         })
         .to_string(),
     };
@@ -212,7 +407,7 @@ pub(crate) async fn process_command(
 }
 
 // TODO: Move this into swall_compositor_config
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub(crate) struct AppControllerResponse {
     pub success: bool,
     pub pid: Option<u32>,
@@ -220,5 +415,11 @@ pub(crate) struct AppControllerResponse {
     pub screen_height: Option<u32>,
     pub config: Option<AppConfig>,
     pub process_ids: Option<Vec<(u32, AppConfig)>>,
+    /// Pairs of pid and the number of times that application has been restarted
+    pub restart_counts: Option<Vec<(u32, u32)>>,
+    /// Pids of the current processes that aren't answering pings
+    pub not_responding: Option<Vec<u32>>,
+    pub logs: Option<Vec<LogLine>>,
+    pub usage: Option<ResourceUsage>,
     pub error: Option<String>,
 }
@@ -0,0 +1,157 @@
+use anyhow::{anyhow, Result};
+
+use crate::config::{Rect, Side, Slot};
+
+/// The area a slot covers. [Slot::Auto] depends on the other tiles so it's worked out with [tile_rects].
+pub fn slot_rect(slot: Slot, screens: &[Rect]) -> Result<Rect> {
+    match slot {
+        Slot::Screen { screen } => screen_rect(screens, screen),
+        Slot::Half { screen, side } => {
+            let screen = screen_rect(screens, screen)?;
+            let (x, width) = split(screen.x, screen.width, 2, (side == Side::Right) as u32);
+            let (y, height) = split(screen.y, screen.height, 2, (side == Side::Bottom) as u32);
+            Ok(match side {
+                Side::Left | Side::Right => Rect { x, width, ..screen },
+                Side::Top | Side::Bottom => Rect {
+                    y,
+                    height,
+                    ..screen
+                },
+            })
+        }
+        Slot::Grid {
+            screen,
+            columns,
+            rows,
+            column,
+            row,
+            column_span,
+            row_span,
+        } => {
+            let screen = screen_rect(screens, screen)?;
+            if column_span == 0 || row_span == 0 {
+                return Err(anyhow!(
+                    "A grid cell has to span at least one column and row"
+                ));
+            }
+            let last_column = column
+                .checked_add(column_span - 1)
+                .filter(|last| *last < columns);
+            let last_row = row.checked_add(row_span - 1).filter(|last| *last < rows);
+            let (Some(last_column), Some(last_row)) = (last_column, last_row) else {
+                return Err(anyhow!(
+                    "Cell at column {column} and row {row} doesn't fit in a {columns}x{rows} grid"
+                ));
+            };
+            let (x, _) = split(screen.x, screen.width, columns, column);
+            let (end_x, end_width) = split(screen.x, screen.width, columns, last_column);
+            let (y, _) = split(screen.y, screen.height, rows, row);
+            let (end_y, end_height) = split(screen.y, screen.height, rows, last_row);
+            Ok(Rect {
+                x,
+                y,
+                width: end_x + end_width - x,
+                height: end_y + end_height - y,
+            })
+        }
+        Slot::Auto { screen } => screen_rect(screens, screen.unwrap_or(0)),
+    }
+}
+
+pub fn screen_rect(screens: &[Rect], screen: usize) -> Result<Rect> {
+    screens.get(screen).copied().ok_or_else(|| {
+        anyhow!(
+            "Screen {screen} doesn't exist. There are {} screens.",
+            screens.len()
+        )
+    })
+}
+
+/// Splits `region` into `count` tiles that cover it exactly. The tiles are laid out in lines along the
+/// longer side of the region, with the first lines getting an extra tile when they don't divide evenly.
+pub fn tile_rects(region: Rect, count: usize) -> Vec<Rect> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let lines = (count as f64).sqrt().ceil() as u32;
+    let per_line = count as u32 / lines;
+    let extra = count as u32 % lines;
+    let portrait = region.height >= region.width;
+
+    let mut tiles = Vec::with_capacity(count);
+    for line in 0..lines {
+        let in_line = per_line + (line < extra) as u32;
+        for position in 0..in_line {
+            let tile = if portrait {
+                let (y, height) = split(region.y, region.height, lines, line);
+                let (x, width) = split(region.x, region.width, in_line, position);
+                Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+            } else {
+                let (x, width) = split(region.x, region.width, lines, line);
+                let (y, height) = split(region.y, region.height, in_line, position);
+                Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+            };
+            tiles.push(tile);
+        }
+    }
+    tiles
+}
+
+/// What's left of `area` once `amount` along the `side` edge of `container` is reserved. Areas that don't
+/// overlap `container` are left alone.
+pub fn reserve_edge(area: Rect, container: Rect, side: Side, amount: u32) -> Rect {
+    if !area.overlaps(&container) {
+        return area;
+    }
+    let (right, bottom) = (area.x + area.width, area.y + area.height);
+    match side {
+        Side::Top => {
+            let y = (container.y + amount.min(container.height)).clamp(area.y, bottom);
+            Rect {
+                y,
+                height: bottom - y,
+                ..area
+            }
+        }
+        Side::Bottom => {
+            let edge = container.y + container.height - amount.min(container.height);
+            Rect {
+                height: edge.clamp(area.y, bottom) - area.y,
+                ..area
+            }
+        }
+        Side::Left => {
+            let x = (container.x + amount.min(container.width)).clamp(area.x, right);
+            Rect {
+                x,
+                width: right - x,
+                ..area
+            }
+        }
+        Side::Right => {
+            let edge = container.x + container.width - amount.min(container.width);
+            Rect {
+                width: edge.clamp(area.x, right) - area.x,
+                ..area
+            }
+        }
+    }
+}
+
+/// Start and length of part `index` when `length` starting at `start` is cut into `parts`. Rounding is
+/// spread out so the parts always add up to `length`.
+fn split(start: u32, length: u32, parts: u32, index: u32) -> (u32, u32) {
+    let offset = |index: u32| (length as u64 * index as u64 / parts as u64) as u32;
+    (start + offset(index), offset(index + 1) - offset(index))
+}
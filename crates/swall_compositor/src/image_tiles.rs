@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                Kind,
+            },
+            gles::GlesRenderer,
+        },
+    },
+    utils::{Logical, Rectangle, Size, Transform},
+};
+use tokio::task::JoinHandle;
+
+use crate::config::{FitMode, ImageTile, Rect};
+use crate::raster::{self, Raster};
+
+/// Draws [AppConfig::image](crate::config::AppConfig::image) tiles. Images are decoded off the render loop,
+/// and only the ones showing and the one coming up next are kept in memory.
+#[derive(Debug, Default)]
+pub struct ImageTileRenderer {
+    slideshows: HashMap<u32, Slideshow>,
+}
+
+#[derive(Debug)]
+struct Slideshow {
+    tile: ImageTile,
+    /// Index of the image showing and when it started showing
+    current: Option<(usize, Instant)>,
+    /// Image fading out under the current one
+    previous: Option<usize>,
+    decoded: HashMap<usize, (MemoryRenderBuffer, Size<i32, Logical>)>,
+    pending: HashMap<usize, JoinHandle<Result<Raster>>>,
+    /// Images that couldn't be decoded. They're skipped over instead of being tried again.
+    failed: HashSet<usize>,
+}
+
+impl ImageTileRenderer {
+    /// Render elements for `tiles` (id, area, tile), front to back
+    pub async fn elements(
+        &mut self,
+        renderer: &mut GlesRenderer,
+        tiles: &[(u32, Rect, &ImageTile)],
+    ) -> Result<Vec<MemoryRenderBufferRenderElement<GlesRenderer>>> {
+        self.slideshows
+            .retain(|id, _| tiles.iter().any(|(tile_id, ..)| tile_id == id));
+
+        let mut elements = Vec::new();
+        for (id, area, tile) in tiles {
+            let slideshow = self
+                .slideshows
+                .entry(*id)
+                .or_insert_with(|| Slideshow::new((*tile).clone()));
+            if slideshow.tile != **tile {
+                *slideshow = Slideshow::new((*tile).clone());
+            }
+
+            slideshow.collect_decoded(*id).await;
+            slideshow.advance();
+            elements.extend(slideshow.elements(renderer, *area)?);
+        }
+        Ok(elements)
+    }
+}
+
+impl Slideshow {
+    fn new(tile: ImageTile) -> Self {
+        Self {
+            tile,
+            current: None,
+            previous: None,
+            decoded: HashMap::new(),
+            pending: HashMap::new(),
+            failed: HashSet::new(),
+        }
+    }
+
+    fn duration(&self, index: usize) -> Duration {
+        Duration::from_millis(
+            self.tile.images[index]
+                .duration_ms
+                .unwrap_or(self.tile.duration_ms),
+        )
+    }
+
+    /// Start decoding an image unless it's already decoded (or being decoded)
+    fn decode(&mut self, index: usize) {
+        if self.decoded.contains_key(&index)
+            || self.pending.contains_key(&index)
+            || self.failed.contains(&index)
+        {
+            return;
+        }
+        let path = self.tile.images[index].path.clone();
+        self.pending.insert(
+            index,
+            tokio::task::spawn_blocking(move || raster::decode_image(&path)),
+        );
+    }
+
+    /// Take in the images that finished decoding. Ones that couldn't be decoded, including ones whose
+    /// decoder crashed, are marked as failed.
+    async fn collect_decoded(&mut self, id: u32) {
+        let finished: Vec<usize> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.is_finished())
+            .map(|(index, _)| *index)
+            .collect();
+        for index in finished {
+            let Some(pending) = self.pending.remove(&index) else {
+                continue;
+            };
+            match pending.await {
+                Ok(Ok(raster)) => {
+                    let size = Size::from((raster.width as i32, raster.height as i32));
+                    let buffer = MemoryRenderBuffer::from_slice(
+                        &raster.rgba,
+                        Fourcc::Abgr8888,
+                        (size.w, size.h),
+                        1,
+                        Transform::Normal,
+                        None,
+                    );
+                    self.decoded.insert(index, (buffer, size));
+                }
+                Ok(Err(error)) => {
+                    println!("Can't show image {index} of image tile {id}: {error}");
+                    self.failed.insert(index);
+                }
+                Err(error) => {
+                    println!("Decoding image {index} of image tile {id} failed: {error}");
+                    self.failed.insert(index);
+                }
+            }
+        }
+    }
+
+    /// Move on to the next image once the current one has been up long enough and the next one is decoded.
+    /// Images that failed to decode are skipped over. Once every image has failed nothing is shown.
+    fn advance(&mut self) {
+        let due = match self.current {
+            None => self.next_image(None),
+            Some((index, since)) if since.elapsed() >= self.duration(index) => {
+                self.next_image(Some(index)).filter(|next| *next != index)
+            }
+            Some(_) => None,
+        };
+        if let Some(due) = due {
+            if self.decoded.contains_key(&due) {
+                self.previous = self.current.map(|(index, _)| index);
+                self.current = Some((due, Instant::now()));
+            } else {
+                self.decode(due);
+            }
+        }
+
+        let Some((current, since)) = self.current else {
+            return;
+        };
+        if since.elapsed() >= Duration::from_millis(self.tile.crossfade_ms) {
+            self.previous = None;
+        }
+
+        // Have the next image ready by the time it's due
+        let next = self.next_image(Some(current));
+        if let Some(next) = next {
+            self.decode(next);
+        }
+        let previous = self.previous;
+        self.decoded.retain(|index, _| {
+            *index == current || Some(*index) == next || Some(*index) == previous
+        });
+    }
+
+    /// The first image after `after` (or from the start) that hasn't failed to decode, wrapping around.
+    /// `None` if every image failed.
+    fn next_image(&self, after: Option<usize>) -> Option<usize> {
+        let count = self.tile.images.len();
+        let start = after.map_or(0, |index| index + 1);
+        (start..start + count)
+            .map(|index| index % count)
+            .find(|index| !self.failed.contains(index))
+    }
+
+    fn elements(
+        &self,
+        renderer: &mut GlesRenderer,
+        area: Rect,
+    ) -> Result<Vec<MemoryRenderBufferRenderElement<GlesRenderer>>> {
+        let mut elements = Vec::new();
+        let Some((current, since)) = self.current else {
+            return Ok(elements);
+        };
+
+        // The current image fades in over the previous one
+        let fade = match self.previous {
+            Some(_) => {
+                (since.elapsed().as_secs_f32() / (self.tile.crossfade_ms as f32 / 1000.0)).min(1.0)
+            }
+            None => 1.0,
+        };
+        for (index, alpha) in [(Some(current), fade), (self.previous, 1.0)] {
+            let Some((buffer, size)) = index.and_then(|index| self.decoded.get(&index)) else {
+                continue;
+            };
+            let (src, dest) = fit(self.tile.fit, area, *size);
+            elements.push(MemoryRenderBufferRenderElement::from_buffer(
+                renderer,
+                (dest.loc.x as f64, dest.loc.y as f64),
+                buffer,
+                Some(alpha),
+                Some(src),
+                Some(dest.size),
+                Kind::Unspecified,
+            )?);
+        }
+        Ok(elements)
+    }
+}
+
+/// The part of an image of `size` that is shown, and where on the canvas it goes, to fit it into `area`
+fn fit(
+    mode: FitMode,
+    area: Rect,
+    size: Size<i32, Logical>,
+) -> (Rectangle<f64, Logical>, Rectangle<i32, Logical>) {
+    let whole_image = Rectangle::from_loc_and_size((0.0, 0.0), size.to_f64());
+    let whole_area = Rectangle::from_loc_and_size(
+        (area.x as i32, area.y as i32),
+        (area.width as i32, area.height as i32),
+    );
+    let (area_width, area_height) = (area.width as f64, area.height as f64);
+    let (image_width, image_height) = (size.w as f64, size.h as f64);
+
+    match mode {
+        FitMode::Stretch => (whole_image, whole_area),
+        FitMode::Contain => {
+            let scale = (area_width / image_width).min(area_height / image_height);
+            let (width, height) = (image_width * scale, image_height * scale);
+            let dest = Rectangle::from_loc_and_size(
+                (
+                    area.x as i32 + ((area_width - width) / 2.0) as i32,
+                    area.y as i32 + ((area_height - height) / 2.0) as i32,
+                ),
+                (width.round() as i32, height.round() as i32),
+            );
+            (whole_image, dest)
+        }
+        FitMode::Cover => {
+            let scale = (area_width / image_width).max(area_height / image_height);
+            let (width, height) = (area_width / scale, area_height / scale);
+            let src = Rectangle::from_loc_and_size(
+                ((image_width - width) / 2.0, (image_height - height) / 2.0),
+                (width, height),
+            );
+            (src, whole_area)
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::sync::Mutex;
+
+use smithay::{
+    backend::renderer::{
+        element::{
+            surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+            utils::CropRenderElement,
+            Kind,
+        },
+        gles::GlesRenderer,
+    },
+    delegate_layer_shell, delegate_output,
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::wayland_server::{
+        protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+        DisplayHandle,
+    },
+    utils::{Logical, Physical, Point, Rectangle, Transform},
+    wayland::{
+        compositor::with_states,
+        output::OutputHandler,
+        shell::wlr_layer::{
+            Anchor, ExclusiveZone, Layer, LayerSurface, LayerSurfaceCachedState, LayerSurfaceData,
+            WlrLayerShellHandler, WlrLayerShellState,
+        },
+    },
+};
+
+use crate::config::{Rect, Side};
+use crate::{surface_under, App, ClientState, SwallRenderElement};
+
+/// The rect a layer surface is anchored in. One of the screens, or the whole canvas if the client didn't
+/// pick an output. Only set on layer surfaces of allowed clients.
+#[derive(Debug, Clone, Copy)]
+struct LayerContainer(Rect);
+
+/// The exclusive zone of a layer surface as of its last commit, to tell when it changes
+#[derive(Debug, Default)]
+struct CommittedZone(Mutex<Option<(Rect, Side, u32)>>);
+
+/// Layers drawn over the applications, front to back
+pub(crate) const UPPER_LAYERS: [Layer; 2] = [Layer::Overlay, Layer::Top];
+/// Layers drawn under the applications, front to back
+pub(crate) const LOWER_LAYERS: [Layer; 2] = [Layer::Bottom, Layer::Background];
+
+impl WlrLayerShellHandler for App {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.layer_shell_state
+    }
+
+    fn new_layer_surface(
+        &mut self,
+        surface: LayerSurface,
+        output: Option<WlOutput>,
+        _layer: Layer,
+        namespace: String,
+    ) {
+        let pid = surface
+            .wl_surface()
+            .client()
+            .and_then(|client| Some(client.get_data::<ClientState>()?.client_pid));
+        let name = pid.and_then(|pid| self.app_controller.app_name_blocking(pid as u32));
+        if !name.is_some_and(|name| self.layer_shell.allowed.contains(&name)) {
+            println!("Layer surface '{namespace}' of pid {pid:?} isn't allowed. Closing it.");
+            surface.send_close();
+            return;
+        }
+
+        let container = output
+            .as_ref()
+            .and_then(Output::from_resource)
+            .and_then(|output| self.outputs.iter().position(|known| *known == output))
+            .map_or(self.canvas, |screen| self.screens[screen]);
+        with_states(surface.wl_surface(), |states| {
+            states
+                .data_map
+                .insert_if_missing_threadsafe(|| LayerContainer(container));
+            states
+                .data_map
+                .insert_if_missing_threadsafe(CommittedZone::default);
+        });
+    }
+
+    fn layer_destroyed(&mut self, surface: LayerSurface) {
+        self.update_exclusive_zones(Some(surface.wl_surface()));
+    }
+}
+
+// Outputs only tell clients where the screens are. Nothing is rendered per output.
+impl OutputHandler for App {}
+
+delegate_layer_shell!(App);
+delegate_output!(App);
+
+/// Advertise every screen as an output so layer shell clients can anchor themselves to a single screen
+pub(crate) fn create_outputs(dh: &DisplayHandle, screens: &[Rect]) -> Vec<Output> {
+    screens
+        .iter()
+        .enumerate()
+        .map(|(index, screen)| {
+            let output = Output::new(
+                format!("SCREEN-{index}"),
+                PhysicalProperties {
+                    size: (0, 0).into(),
+                    subpixel: Subpixel::Unknown,
+                    make: "sWall".to_string(),
+                    model: "Screen".to_string(),
+                },
+            );
+            let mode = Mode {
+                size: (screen.width as i32, screen.height as i32).into(),
+                refresh: 60_000,
+            };
+            output.change_current_state(
+                Some(mode),
+                Some(Transform::Normal),
+                None,
+                Some((screen.x as i32, screen.y as i32).into()),
+            );
+            output.set_preferred(mode);
+            output.create_global::<App>(dh);
+            output
+        })
+        .collect()
+}
+
+impl App {
+    /// Layer surfaces of allowed clients on `layers` along with where they are on the canvas. Front to back.
+    fn layer_surfaces(&self, layers: &[Layer]) -> Vec<(LayerSurface, Rectangle<i32, Logical>)> {
+        let mut found = Vec::new();
+        for layer in layers {
+            // Surfaces made later are drawn over earlier ones
+            for surface in self.layer_shell_state.layer_surfaces().rev() {
+                let geometry = with_states(surface.wl_surface(), |states| {
+                    let container = states.data_map.get::<LayerContainer>()?.0;
+                    let state = *states.cached_state.current::<LayerSurfaceCachedState>();
+                    (state.layer == *layer).then(|| layer_geometry(container, &state))
+                });
+                if let Some(geometry) = geometry {
+                    found.push((surface, geometry));
+                }
+            }
+        }
+        found
+    }
+
+    /// Render elements for the layer surfaces on `layers`, front to back
+    pub(crate) fn layer_elements(
+        &self,
+        renderer: &mut GlesRenderer,
+        layers: &[Layer],
+    ) -> Vec<SwallRenderElement> {
+        let mut elements = Vec::new();
+        for (surface, geometry) in self.layer_surfaces(layers) {
+            let crop: Rectangle<i32, Physical> = geometry.to_physical(1);
+            let surface_elements: Vec<WaylandSurfaceRenderElement<GlesRenderer>> =
+                render_elements_from_surface_tree(
+                    renderer,
+                    surface.wl_surface(),
+                    geometry.loc.to_physical(1),
+                    1.0,
+                    1.0,
+                    Kind::Unspecified,
+                );
+            elements.extend(
+                surface_elements
+                    .into_iter()
+                    .filter_map(|e| CropRenderElement::from_element(e, 1.0, crop))
+                    .map(SwallRenderElement::from),
+            );
+        }
+        elements
+    }
+
+    /// The top most surface of a layer surface on `layers` that accepts input at `location`
+    pub(crate) fn layer_surface_under(
+        &self,
+        layers: &[Layer],
+        location: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        self.layer_surfaces(layers)
+            .into_iter()
+            .filter(|(_, geometry)| geometry.to_f64().contains(location))
+            .find_map(|(surface, geometry)| {
+                surface_under(surface.wl_surface(), geometry.loc, location)
+            })
+    }
+
+    /// All surfaces of layer surfaces, for sending frame callbacks
+    pub(crate) fn layer_wl_surfaces(&self) -> Vec<WlSurface> {
+        self.layer_shell_state
+            .layer_surfaces()
+            .map(|surface| surface.wl_surface().clone())
+            .collect()
+    }
+
+    /// Configure a layer surface with the size it gets from its anchors whenever that changes, and update the
+    /// exclusive zones if its zone changed. Called on every commit, which is also how the initial configure
+    /// is sent.
+    pub(crate) fn layer_surface_committed(&mut self, surface: &WlSurface) {
+        let Some(layer_surface) = self
+            .layer_shell_state
+            .layer_surfaces()
+            .find(|layer_surface| layer_surface.wl_surface() == surface)
+        else {
+            return;
+        };
+
+        let Some((container, state, initial_configure_sent, zone_changed)) =
+            with_states(surface, |states| {
+                let container = states.data_map.get::<LayerContainer>()?.0;
+                let state = *states.cached_state.current::<LayerSurfaceCachedState>();
+                let zone = exclusive_zone(container, &state);
+                let committed = &states.data_map.get::<CommittedZone>()?.0;
+                let zone_changed = std::mem::replace(&mut *committed.lock().unwrap(), zone) != zone;
+                Some((
+                    container,
+                    state,
+                    states
+                        .data_map
+                        .get::<LayerSurfaceData>()?
+                        .lock()
+                        .unwrap()
+                        .initial_configure_sent,
+                    zone_changed,
+                ))
+            })
+        else {
+            return;
+        };
+
+        let size = layer_geometry(container, &state).size;
+        let changed =
+            layer_surface.with_pending_state(|pending| pending.size.replace(size) != Some(size));
+        if changed || !initial_configure_sent {
+            layer_surface.send_configure();
+        }
+
+        if zone_changed {
+            self.update_exclusive_zones(None);
+        }
+    }
+
+    /// Tell the app controller about changed exclusive zones so tiled applications make room for them.
+    /// `destroyed` is left out since it's still in the list while it's being destroyed.
+    fn update_exclusive_zones(&mut self, destroyed: Option<&WlSurface>) {
+        let zones: Vec<(Rect, Side, u32)> = self
+            .layer_shell_state
+            .layer_surfaces()
+            .filter(|surface| Some(surface.wl_surface()) != destroyed)
+            .filter_map(|surface| {
+                with_states(surface.wl_surface(), |states| {
+                    let container = states.data_map.get::<LayerContainer>()?.0;
+                    exclusive_zone(
+                        container,
+                        &states.cached_state.current::<LayerSurfaceCachedState>(),
+                    )
+                })
+            })
+            .collect();
+
+        if zones != self.exclusive_zones {
+            self.exclusive_zones = zones.clone();
+            let app_controller = self.app_controller.clone();
+            tokio::spawn(async move { app_controller.set_exclusive_zones(&zones).await });
+        }
+    }
+}
+
+/// Where a layer surface goes inside `container` given its anchors, margins and requested size. A size of
+/// zero along an axis it's anchored to both ends of stretches it across the container.
+fn layer_geometry(container: Rect, state: &LayerSurfaceCachedState) -> Rectangle<i32, Logical> {
+    let anchor = state.anchor;
+    let margin = state.margin;
+
+    let place = |start: u32,
+                 length: u32,
+                 requested: i32,
+                 (before, after): (bool, bool),
+                 (margin_before, margin_after): (i32, i32)| {
+        let (start, length) = (start as i32, length as i32);
+        let available = length - margin_before - margin_after;
+        let size = match requested {
+            0 if before && after => available,
+            requested => requested,
+        };
+        let position = match (before, after) {
+            (true, false) => start + margin_before,
+            (false, true) => start + length - margin_after - size,
+            _ => start + margin_before + (available - size) / 2,
+        };
+        (position, size.max(0))
+    };
+
+    let (x, width) = place(
+        container.x,
+        container.width,
+        state.size.w,
+        (
+            anchor.contains(Anchor::LEFT),
+            anchor.contains(Anchor::RIGHT),
+        ),
+        (margin.left, margin.right),
+    );
+    let (y, height) = place(
+        container.y,
+        container.height,
+        state.size.h,
+        (
+            anchor.contains(Anchor::TOP),
+            anchor.contains(Anchor::BOTTOM),
+        ),
+        (margin.top, margin.bottom),
+    );
+    Rectangle::from_loc_and_size((x, y), (width, height))
+}
+
+/// The edge of `container` a layer surface reserves and how much of it. Only surfaces anchored to a single
+/// edge, or to an edge and both of its neighbours, can reserve space.
+fn exclusive_zone(container: Rect, state: &LayerSurfaceCachedState) -> Option<(Rect, Side, u32)> {
+    let ExclusiveZone::Exclusive(zone) = state.exclusive_zone else {
+        return None;
+    };
+    let anchor = state.anchor;
+    let horizontal = Anchor::LEFT | Anchor::RIGHT;
+    let vertical = Anchor::TOP | Anchor::BOTTOM;
+    let (side, margin) = if anchor == Anchor::TOP || anchor == Anchor::TOP | horizontal {
+        (Side::Top, state.margin.top)
+    } else if anchor == Anchor::BOTTOM || anchor == Anchor::BOTTOM | horizontal {
+        (Side::Bottom, state.margin.bottom)
+    } else if anchor == Anchor::LEFT || anchor == Anchor::LEFT | vertical {
+        (Side::Left, state.margin.left)
+    } else if anchor == Anchor::RIGHT || anchor == Anchor::RIGHT | vertical {
+        (Side::Right, state.margin.right)
+    } else {
+        return None;
+    };
+    Some((container, side, zone + margin.max(0) as u32))
+}
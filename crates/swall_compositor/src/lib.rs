@@ -1,9 +1,11 @@
 #![deny(unused_crate_dependencies)]
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::ErrorKind;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use gstreamer::{Buffer, Caps, ReferenceTimestampMeta};
@@ -14,19 +16,24 @@ use smithay::{
         renderer::{
             damage::OutputDamageTracker,
             element::{
+                memory::MemoryRenderBufferRenderElement,
+                render_elements,
+                solid::{SolidColorBuffer, SolidColorRenderElement},
                 surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+                utils::CropRenderElement,
                 Kind,
             },
             gles::{GlesRenderer, GlesTexture},
-            utils::on_commit_buffer_handler,
+            utils::{on_commit_buffer_handler, RendererSurfaceStateUserData},
             ExportMem, Offscreen,
         },
     },
-    delegate_compositor, delegate_seat, delegate_shm,
+    delegate_compositor, delegate_seat, delegate_shm, delegate_xdg_decoration,
     input::{
         pointer::{ButtonEvent, MotionEvent, PointerHandle},
         Seat, SeatHandler, SeatState,
     },
+    output::Output,
     reexports::{
         gbm::Format,
         wayland_protocols::xdg::{
@@ -38,36 +45,67 @@ use smithay::{
                 wl_buffer, wl_seat,
                 wl_surface::{self, WlSurface},
             },
-            Client, Display,
+            Client, Display, Resource,
         },
     },
-    utils::{Logical, Point, Rectangle, Serial, Size, Transform, SERIAL_COUNTER},
+    utils::{Logical, Physical, Point, Rectangle, Serial, Size, Transform, SERIAL_COUNTER},
     wayland::{
         buffer::BufferHandler,
         compositor::{
             self as smithay_compositor, with_surface_tree_downward, CompositorClientState,
-            CompositorHandler, CompositorState, SurfaceAttributes, TraversalAction,
+            CompositorHandler, CompositorState, SubsurfaceCachedState, SurfaceAttributes,
+            SurfaceData, TraversalAction,
         },
+        output::OutputManagerState,
+        shell::wlr_layer::WlrLayerShellState,
         shell::xdg::{
-            PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
+            decoration::{XdgDecorationHandler, XdgDecorationState},
+            PopupSurface, PositionerState, ShellClient, SurfaceCachedState, ToplevelSurface,
+            XdgShellHandler, XdgShellState, XdgToplevelSurfaceData,
         },
         shm::{ShmHandler, ShmState},
     },
 };
-use tokio::{sync::oneshot, task};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task,
+};
 
 mod compositor;
 pub mod config;
 mod controller;
+mod image_tiles;
+mod layer_shell;
+mod mirror;
+mod overlay;
+mod raster;
 pub mod util;
+pub mod video;
 
-use crate::config::{AppConfig, CompositorConfig};
-use crate::controller::{start_controller_socket, AppController};
+use crate::config::{
+    CompositorConfig, IdleConfig, KillSignal, LayerShellConfig, PingConfig, Rect, ShutdownConfig,
+    Side,
+};
+use crate::controller::{
+    scheduled_entry, start_child_reaper, start_controller_socket, start_idle_switcher,
+    start_launch, start_schedule, AppController,
+};
+use crate::image_tiles::ImageTileRenderer;
+use crate::layer_shell::{create_outputs, LOWER_LAYERS, UPPER_LAYERS};
+use crate::mirror::{mirror_elements, MirrorIds, MirrorRenderElement};
+use crate::overlay::OverlayRenderer;
 use crate::util::ListeningSocket;
+use crate::video::{VideoInputs, VideoRenderer};
+
+/// How often children are checked while waiting for them to exit during shutdown
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// Hardcoded since [swall_gst_compositor] only supports one size
 pub const HARDCODED_COMPOSITOR_SIZE: [u32; 2] = [4320, 1920];
 
+/// Linux input code of the left mouse button, which touches are passed on as
+const BTN_LEFT: u32 = 0x110;
+
 impl BufferHandler for App {
     fn buffer_destroyed(&mut self, _buffer: &wl_buffer::WlBuffer) {}
 }
@@ -81,6 +119,34 @@ impl XdgShellHandler for App {
         &mut self.xdg_shell_state
     }
 
+    fn new_client(&mut self, client: ShellClient) {
+        let client_pid = self
+            .client_pid
+            .expect("'wl_dispatch_intercept' not wrapping wayland dispatch event")
+            .try_into()
+            .unwrap();
+        self.ping_states.insert(
+            client_pid,
+            PingState {
+                client,
+                last_ping: Instant::now(),
+                pending: None,
+                responsive: true,
+            },
+        );
+    }
+
+    fn client_pong(&mut self, _client: ShellClient) {
+        let client_pid: u32 = self
+            .client_pid
+            .expect("'wl_dispatch_intercept' not wrapping wayland dispatch event")
+            .try_into()
+            .unwrap();
+        if let Some(ping) = self.ping_states.get_mut(&client_pid) {
+            ping.pending = None;
+        }
+    }
+
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
         let client_pid = self
             .client_pid
@@ -91,7 +157,7 @@ impl XdgShellHandler for App {
         // TODO: Handle multiple windows
         let app_pos_opt = self
             .application_viewer
-            .application_rect_by_pid_blocking(client_pid);
+            .application_content_rect_by_pid_blocking(client_pid);
 
         let should_add_pid = surface.with_pending_state(|state| {
             if let Some(app_pos) = app_pos_opt {
@@ -128,6 +194,7 @@ impl XdgShellHandler for App {
                     .data_map
                     .insert_if_missing_threadsafe(|| SurfacePid(client_pid)));
             });
+            self.app_controller.notify_window_mapped(client_pid);
         }
 
         // Sends all the state updates above to the wayland clients
@@ -152,6 +219,46 @@ impl XdgShellHandler for App {
     }
 }
 
+// Tells clients that the compositor will handle decorations so they don't draw their own titlebars. We
+// always insist on server side decorations, whatever the client asks for.
+impl XdgDecorationHandler for App {
+    fn new_decoration(&mut self, toplevel: ToplevelSurface) {
+        force_server_side_decorations(&toplevel);
+    }
+
+    fn request_mode(
+        &mut self,
+        toplevel: ToplevelSurface,
+        _mode: zxdg_toplevel_decoration_v1::Mode,
+    ) {
+        force_server_side_decorations(&toplevel);
+    }
+
+    fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        force_server_side_decorations(&toplevel);
+    }
+}
+
+fn force_server_side_decorations(toplevel: &ToplevelSurface) {
+    toplevel.with_pending_state(|state| {
+        state.decoration_mode = Some(zxdg_toplevel_decoration_v1::Mode::ServerSide);
+    });
+
+    // Before the initial configure the mode is sent along with it from [XdgShellHandler::new_toplevel]
+    let initial_configure_sent = smithay_compositor::with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .initial_configure_sent
+    });
+    if initial_configure_sent {
+        toplevel.send_configure();
+    }
+}
+
 // Compositor wayland protocol is required since this is a compositor. Smithay will handle the rest.
 impl CompositorHandler for App {
     fn compositor_state(&mut self) -> &mut CompositorState {
@@ -164,6 +271,7 @@ impl CompositorHandler for App {
 
     fn commit(&mut self, surface: &WlSurface) {
         on_commit_buffer_handler::<Self>(surface);
+        self.layer_surface_committed(surface);
     }
 }
 
@@ -197,6 +305,9 @@ pub(crate) struct App {
     compositor_state: CompositorState,
 
     xdg_shell_state: XdgShellState,
+    _xdg_decoration_state: XdgDecorationState,
+    layer_shell_state: WlrLayerShellState,
+    _output_manager_state: OutputManagerState,
     shm_state: ShmState,
     seat_state: SeatState<Self>,
 
@@ -206,33 +317,68 @@ pub(crate) struct App {
 
     /// This should always be present inside [XdgShellHandler] methods.
     client_pid: Option<i32>,
+    /// Clients that bound `xdg_wm_base` by pid, so they can be pinged
+    ping_states: HashMap<u32, PingState>,
+
+    layer_shell: LayerShellConfig,
+    /// One output per screen, in the same order as [App::screens]
+    outputs: Vec<Output>,
+    screens: Vec<Rect>,
+    canvas: Rect,
+    /// Exclusive zones of layer surfaces the app controller was last told about
+    exclusive_zones: Vec<(Rect, Side, u32)>,
+}
+
+/// Whether a client answers the pings sent to it
+struct PingState {
+    client: ShellClient,
+    last_ping: Instant,
+    /// When the ping that hasn't been answered yet was sent
+    pending: Option<Instant>,
+    responsive: bool,
 }
 
 impl App {
     pub(crate) async fn get_surface_at_pos(
         &self,
         location: Point<f64, Logical>,
-    ) -> Option<(&WlSurface, Point<i32, Logical>)> {
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        if location.x < 0.0 || location.y < 0.0 {
+            return None;
+        }
+
+        // Layer surfaces above the applications get input first
+        if let Some(hit) = self.layer_surface_under(&UPPER_LAYERS, location) {
+            return Some(hit);
+        }
+
+        let placements = self.application_viewer.snapshot().await;
+
         // Reversed since surfaces are rendered front to back so the later ones will be ontop
         for top_surface in self.xdg_shell_state.toplevel_surfaces().iter().rev() {
             let surface = top_surface.wl_surface();
-            let app_config = smithay_compositor::with_states(surface, |surface_data| {
-                surface_data.data_map.get::<SurfacePid>().unwrap().0
-            });
+            let Some(SurfacePid(pid)) = smithay_compositor::with_states(surface, |surface_data| {
+                surface_data.data_map.get::<SurfacePid>().copied()
+            }) else {
+                continue;
+            };
 
-            let Some(rect) = self
-                .application_viewer
-                .application_rect_by_pid(app_config)
-                .await
-            else {
+            let Some(rect) = placements.visible_content_rect(pid) else {
                 continue;
             };
 
-            if rect.is_inside(location.x as u32, location.y as u32) {
-                return Some((surface, Point::from((rect.x as i32, rect.y as i32))));
+            // Anything outside the rect is cropped away when rendering so it can't be clicked either
+            if !rect.is_inside(location.x as u32, location.y as u32) {
+                continue;
+            }
+
+            // If no surface in the tree accepts input here the click falls through to the app underneath
+            if let Some(hit) = surface_under(surface, surface_origin(surface, rect), location) {
+                return Some(hit);
             }
         }
-        None
+
+        self.layer_surface_under(&LOWER_LAYERS, location)
     }
 
     /// [smithay] does not pass a [Client] to handler methods (such as [XdgShellHandler::new_toplevel]). To get
@@ -253,8 +399,6 @@ impl App {
 
 pub struct Compositor {
     state: App,
-    /// TODO: Shouldn't be in here. Move this out of the compositor
-    launch_config: Vec<AppConfig>,
     gles_renderer: GlesRenderer,
     damage_tracker: OutputDamageTracker,
     texture: GlesTexture,
@@ -263,10 +407,36 @@ pub struct Compositor {
     size_buffer: Size<i32, Logical>,
     display: Display<App>,
     pointer: PointerHandle<App>,
+    pointer_grab: Option<PointerGrab>,
+    /// Solid colour buffers for the top, bottom, left and right edges of each application's border. Kept
+    /// between frames so unchanged borders are not damaged every frame.
+    border_buffers: HashMap<u32, [SolidColorBuffer; 4]>,
+    /// Buffers covering applications that are hung or restarting
+    placeholder_buffers: HashMap<u32, SolidColorBuffer>,
+    /// Kept between frames so mirrors aren't damaged every frame
+    mirror_ids: MirrorIds,
+    overlay: OverlayRenderer,
+    image_tiles: ImageTileRenderer,
+    /// Draws the images of [config::Background], one tile per screen
+    background: ImageTileRenderer,
+    /// Video streams fed in from outside of Wayland, see [Compositor::set_video_inputs]
+    video_inputs: VideoInputs,
+    video: VideoRenderer,
+    ping: PingConfig,
+    shutdown: ShutdownConfig,
+    idle: Option<IdleConfig>,
+    /// When the last navigation event came in
+    last_interaction: Instant,
+    /// Touch points that are down, the one that's been down the longest first. Only it moves the pointer.
+    touches: Vec<u32>,
+    /// Whether the attract layout is showing, or is about to be
+    is_idle: bool,
+    /// Where switches to and from the attract layout are sent, see [start_idle_switcher]
+    idle_switches: mpsc::UnboundedSender<bool>,
     unix_socket_handle: oneshot::Receiver<std::io::Error>,
 
     /// Closes controller when compositor is dropped
-    _controller_cancel_token: tokio::sync::broadcast::Sender<()>,
+    controller_cancel_token: tokio::sync::broadcast::Sender<()>,
 }
 
 impl Compositor {
@@ -284,19 +454,39 @@ impl Compositor {
         let seat = seat_state.new_wl_seat(&dh, "winit");
 
         let compositor_app_handle = compositor::CompositorApplicationHandle::new();
+        for widget in &config.overlay {
+            compositor_app_handle.set_overlay(widget.clone()).await;
+        }
+        compositor_app_handle
+            .set_background(config.background.clone())
+            .await;
 
-        let app_controller = Arc::new(AppController::new(compositor_app_handle.clone()));
+        let app_controller = Arc::new(AppController::new(compositor_app_handle.clone(), config));
 
         // Build the root object that holds all wayland state so that it can be accessed from the callbacks
         let mut state = App {
             compositor_state,
             xdg_shell_state: XdgShellState::new::<App>(&dh),
+            _xdg_decoration_state: XdgDecorationState::new::<App>(&dh),
+            layer_shell_state: WlrLayerShellState::new::<App>(&dh),
+            _output_manager_state: OutputManagerState::new_with_xdg_output::<App>(&dh),
             shm_state,
             seat_state,
             seat,
             app_controller,
             application_viewer: compositor_app_handle.view(),
             client_pid: None,
+            ping_states: HashMap::new(),
+            layer_shell: config.layer_shell.clone(),
+            outputs: create_outputs(&dh, &config.screen_rects()),
+            screens: config.screen_rects(),
+            canvas: Rect {
+                x: 0,
+                y: 0,
+                width: config.width,
+                height: config.height,
+            },
+            exclusive_zones: Vec::new(),
         };
 
         // Wayland's protocol communicates over a socket file. Applications will search in the folder specified in the
@@ -365,11 +555,45 @@ impl Compositor {
         });
 
         // Start the app controller logic to send and receive state modifying commands
-        let controller_cancel_token =
-            start_controller_socket(state.app_controller.clone(), [config.width, config.height])
-                .await?;
+        let controller_cancel_token = start_controller_socket(
+            state.app_controller.clone(),
+            [config.width, config.height],
+            config.controller_access.clone(),
+        )
+        .await?;
         println!("App Controller Successfully Started.");
 
+        // Clean up after apps that crash or close on their own
+        start_child_reaper(
+            state.app_controller.clone(),
+            controller_cancel_token.subscribe(),
+        );
+
+        // Start the apps from the config. Anything that fails is reported without stopping the compositor.
+        // A scheduled layout takes over the whole wall, so there's no point starting them if one is due.
+        if scheduled_entry(&config.schedule).is_none() {
+            start_launch(
+                state.app_controller.clone(),
+                config.launch.clone(),
+                controller_cancel_token.subscribe(),
+            );
+        }
+        start_schedule(
+            state.app_controller.clone(),
+            config.layouts.clone(),
+            config.schedule.clone(),
+            controller_cancel_token.subscribe(),
+        );
+        let idle_switches = start_idle_switcher(
+            state.app_controller.clone(),
+            config
+                .idle
+                .as_ref()
+                .map(|idle| idle.attract.clone())
+                .unwrap_or_default(),
+            controller_cancel_token.subscribe(),
+        );
+
         // TODO: Choose a device in a smarter way. Is the first one always the best? (maybe)
         // Create an opengl-es device for rendering frames on the gpu (or in software sometimes)
         let egl_devices = EGLDevice::enumerate()?.collect::<Vec<_>>();
@@ -419,7 +643,6 @@ impl Compositor {
             .unwrap();
 
         Ok(Self {
-            launch_config: config.launch.clone(),
             state,
             gles_renderer,
             damage_tracker,
@@ -429,11 +652,32 @@ impl Compositor {
             size_buffer,
             display,
             pointer,
+            pointer_grab: None,
+            border_buffers: HashMap::new(),
+            placeholder_buffers: HashMap::new(),
+            mirror_ids: MirrorIds::default(),
+            overlay: OverlayRenderer::default(),
+            image_tiles: ImageTileRenderer::default(),
+            background: ImageTileRenderer::default(),
+            video_inputs: VideoInputs::default(),
+            video: VideoRenderer::default(),
+            ping: config.ping,
+            shutdown: config.shutdown,
+            idle: config.idle.clone(),
+            last_interaction: Instant::now(),
+            touches: Vec::new(),
+            is_idle: false,
+            idle_switches,
             unix_socket_handle,
-            _controller_cancel_token: controller_cancel_token,
+            controller_cancel_token,
         })
     }
 
+    /// Composite the streams of `video_inputs` into every frame, in place of any set before
+    pub fn set_video_inputs(&mut self, video_inputs: VideoInputs) {
+        self.video_inputs = video_inputs;
+    }
+
     // TODO: Use a more specific error type than [anyhow::Error]
     /// Ask the compositor to produce a single frame
     pub async fn generate_frame(&mut self) -> Result<Buffer> {
@@ -446,43 +690,103 @@ impl Compositor {
         // TODO: Don't do this if possible
         tokio::time::sleep(Duration::ZERO).await;
 
-        // TODO: This shouldn't be in here at all
-        if let Some(app_config) = self.launch_config.pop() {
+        // Collect windows (or surfaces in general like cursors) that need to be rendered.
+        let top_level_surfaces = self.state.xdg_shell_state.toplevel_surfaces();
+        let mut elements: Vec<SwallRenderElement> = Vec::with_capacity(top_level_surfaces.len());
+
+        // Everything is drawn from the same placements so changes made together land in the same frame
+        let placements = self.state.application_viewer.snapshot().await;
+
+        // Elements are drawn front to back so layer surfaces above the applications go first, followed by
+        // placeholders to cover the applications
+        elements.extend(
             self.state
-                .app_controller
-                .spawn_process(&app_config)
-                .await
-                .unwrap();
+                .layer_elements(&mut self.gles_renderer, &UPPER_LAYERS),
+        );
+        let placeholders = placements.placeholders();
+        self.placeholder_buffers.retain(|pid, _| {
+            placeholders
+                .iter()
+                .any(|(placeholder, ..)| placeholder == pid)
+        });
+        for (pid, rect, color) in placeholders {
+            let buffer = self
+                .placeholder_buffers
+                .entry(pid)
+                .or_insert_with(|| SolidColorBuffer::new((0, 0), color));
+            buffer.update((rect.width as i32, rect.height as i32), color);
+            elements.push(
+                SolidColorRenderElement::from_buffer(
+                    buffer,
+                    (rect.x as i32, rect.y as i32),
+                    1.0,
+                    1.0,
+                    Kind::Unspecified,
+                )
+                .into(),
+            );
         }
 
-        // Collect windows (or surfaces in general like cursors) that need to be rendered.
-        let top_level_surfaces = self.state.xdg_shell_state.toplevel_surfaces();
-        let mut elements: Vec<WaylandSurfaceRenderElement<GlesRenderer>> =
-            Vec::with_capacity(top_level_surfaces.len());
+        // Mirrors go over the applications, showing the windows of their source again
+        for (id, mirror, source_area) in placements.mirrors() {
+            for surface in &top_level_surfaces {
+                let surface_pid = smithay_compositor::with_states(surface.wl_surface(), |states| {
+                    states.data_map.get::<SurfacePid>().copied()
+                });
+                if surface_pid == Some(SurfacePid(mirror.source_pid)) {
+                    elements.extend(mirror_elements(
+                        &mut self.gles_renderer,
+                        &mut self.mirror_ids,
+                        surface.wl_surface(),
+                        id,
+                        &mirror,
+                        source_area,
+                    ));
+                }
+            }
+        }
+        self.mirror_ids.end_frame();
+
+        let mut bordered_pids = Vec::new();
         for surface in top_level_surfaces {
             if let Some(surface_pid) =
                 smithay_compositor::with_states(surface.wl_surface(), |surface_data| {
                     surface_data.data_map.get::<SurfacePid>().copied()
                 })
             {
-                if let Some(surface_area) = self
-                    .state
-                    .application_viewer
-                    .application_rect_by_pid(surface_pid.0)
-                    .await
-                {
-                    let e = render_elements_from_surface_tree(
-                        &mut self.gles_renderer,
-                        surface.wl_surface(),
-                        (
-                            surface_area.x.try_into().unwrap(),
-                            surface_area.y.try_into().unwrap(),
-                        ),
-                        1.0,
-                        1.0,
-                        Kind::Unspecified,
+                if let Some(surface_area) = placements.visible_content_rect(surface_pid.0) {
+                    // The border fills the space between the content area and the edge of the rect
+                    if let Some(border) = placements.border(surface_pid.0) {
+                        bordered_pids.push(surface_pid.0);
+                        elements.extend(border_elements(
+                            &mut self.border_buffers,
+                            surface_pid.0,
+                            surface_area,
+                            border,
+                        ));
+                    }
+
+                    // Line the window geometry up with the top left of the rect, then clip off anything drawn
+                    // outside of it (client side shadows and such) so it can't bleed into neighbouring apps.
+                    let origin = surface_origin(surface.wl_surface(), surface_area);
+                    let crop: Rectangle<i32, Physical> = Rectangle::from_loc_and_size(
+                        (surface_area.x as i32, surface_area.y as i32),
+                        (surface_area.width as i32, surface_area.height as i32),
+                    );
+                    let e: Vec<WaylandSurfaceRenderElement<GlesRenderer>> =
+                        render_elements_from_surface_tree(
+                            &mut self.gles_renderer,
+                            surface.wl_surface(),
+                            origin.to_physical(1),
+                            1.0,
+                            1.0,
+                            Kind::Unspecified,
+                        );
+                    elements.extend(
+                        e.into_iter()
+                            .filter_map(|e| CropRenderElement::from_element(e, 1.0, crop))
+                            .map(SwallRenderElement::from),
                     );
-                    elements.extend(e);
                 } else {
                     // TODO: Add a warning here? This means there is a surface that is unaccounted for by spawning
                 }
@@ -491,15 +795,111 @@ impl Compositor {
             }
         }
 
+        // Image tiles are drawn by the compositor in place of a window
+        let image_tiles = placements.image_tiles();
+        for (pid, area, _) in &image_tiles {
+            if let Some(border) = placements.border(*pid) {
+                bordered_pids.push(*pid);
+                elements.extend(border_elements(
+                    &mut self.border_buffers,
+                    *pid,
+                    *area,
+                    border,
+                ));
+            }
+        }
+        let image_tile_elements = self
+            .image_tiles
+            .elements(&mut self.gles_renderer, &image_tiles)
+            .await?;
+        elements.extend(
+            image_tile_elements
+                .into_iter()
+                .map(SwallRenderElement::from),
+        );
+
+        // Video streams are drawn like image tiles, under the applications
+        let video_elements = self
+            .video
+            .elements(&mut self.gles_renderer, &self.video_inputs)?;
+        elements.extend(video_elements.into_iter().map(SwallRenderElement::from));
+
+        self.border_buffers
+            .retain(|pid, _| bordered_pids.contains(pid));
+
+        elements.extend(
+            self.state
+                .layer_elements(&mut self.gles_renderer, &LOWER_LAYERS),
+        );
+
+        // The background images go behind everything, the background colour fills in the rest
+        let background_tiles = background_tiles(
+            placements.background(),
+            self.state.canvas,
+            &self.state.screens,
+        );
+        let background_tiles: Vec<_> = background_tiles
+            .iter()
+            .map(|(id, area, tile)| (*id, *area, tile))
+            .collect();
+        let background_elements = self
+            .background
+            .elements(&mut self.gles_renderer, &background_tiles)
+            .await?;
+        elements.extend(
+            background_elements
+                .into_iter()
+                .map(SwallRenderElement::from),
+        );
+
+        // The overlay is drawn over everything else, so it goes in front of the application elements
+        let overlay = self
+            .overlay
+            .elements(&mut self.gles_renderer, placements.overlay())
+            .await?;
+        elements.splice(0..0, overlay.into_iter().map(SwallRenderElement::from));
+
         // This will only re-render parts that have change. Setting age to zero will cause the whole screen to be rendered.
         let render_output = self.damage_tracker.render_output_with(
             &mut self.gles_renderer,
             self.texture.clone(),
             1, // TODO: Is this age correct? Seems like it?
             &elements,
-            [0.67843137254, 0.141176, 0.2235294, 1.0], // Background colour
+            placements.background().color(),
         )?;
 
+        // Ask the windows of applications that are being stopped gracefully to close
+        let close_requests = self.state.application_viewer.take_close_requests().await;
+        if !close_requests.is_empty() {
+            for surface in self.state.xdg_shell_state.toplevel_surfaces() {
+                let surface_pid = smithay_compositor::with_states(surface.wl_surface(), |states| {
+                    states.data_map.get::<SurfacePid>().copied()
+                });
+                if surface_pid.is_some_and(|SurfacePid(pid)| close_requests.contains(&pid)) {
+                    surface.send_close();
+                }
+            }
+        }
+
+        // Windows whose rect changed size (moved by a layout, retiled or given a border) are told their new size
+        for surface in self.state.xdg_shell_state.toplevel_surfaces() {
+            let Some(SurfacePid(pid)) =
+                smithay_compositor::with_states(surface.wl_surface(), |states| {
+                    states.data_map.get::<SurfacePid>().copied()
+                })
+            else {
+                continue;
+            };
+            let Some(area) = placements.content_rect(pid) else {
+                continue;
+            };
+            let size = (area.width as i32, area.height as i32).into();
+            // Windows with a pid had their initial configure sent by [XdgShellHandler::new_toplevel]
+            if surface.with_pending_state(|state| state.size.replace(size) != Some(size)) {
+                surface.send_configure();
+            }
+        }
+
         // Tell the surfaces that they're frame update request was handled
         for surface in self.state.xdg_shell_state.toplevel_surfaces() {
             send_frames_surface_tree(
@@ -507,6 +907,9 @@ impl Compositor {
                 self.start_time.elapsed().as_millis() as u32,
             );
         }
+        for surface in self.state.layer_wl_surfaces() {
+            send_frames_surface_tree(&surface, self.start_time.elapsed().as_millis() as u32);
+        }
 
         // Handle events from the wayland clients (applications)
         // 'block_in_place' informs tokio that we expect this might block (specifically because we blocking_lock on a tokio Mutex)
@@ -516,6 +919,9 @@ impl Compositor {
             Ok(())
         })?;
 
+        self.check_pings().await;
+        self.check_idle();
+
         // TODO: Do this threaded somehow to stop blocking?
         // Don't want half rendered frame
         render_output.sync.wait();
@@ -548,48 +954,303 @@ impl Compositor {
         Ok(buffer)
     }
 
-    pub async fn send_pointer_button(&mut self, event: Event) {
-        let time = (self.start_time.elapsed().as_millis() % (u32::MAX as u128)) as u32;
+    /// Stop every application before the compositor goes away. Windows are asked to close first, then whatever
+    /// is still running after [ShutdownConfig::close_grace_ms] gets SIGTERM, then SIGKILL after
+    /// [ShutdownConfig::term_grace_ms].
+    pub async fn shutdown(&mut self) {
+        println!("Shutting down compositor");
 
-        match event {
-            Event::Button {
-                pointer_x,
-                pointer_y,
-                ..
+        // Nothing new should be started (or restarted) while shutting down
+        let _ = self.controller_cancel_token.send(());
+        let app_controller = self.state.app_controller.clone();
+        app_controller.prepare_shutdown().await;
+
+        for surface in self.state.xdg_shell_state.toplevel_surfaces() {
+            surface.send_close();
+        }
+        if self
+            .wait_for_children(Duration::from_millis(self.shutdown.close_grace_ms))
+            .await
+        {
+            return;
+        }
+
+        app_controller.signal_all(KillSignal::Term).await;
+        if self
+            .wait_for_children(Duration::from_millis(self.shutdown.term_grace_ms))
+            .await
+        {
+            return;
+        }
+
+        app_controller.kill_all().await;
+    }
+
+    /// Wait up to `timeout` for every child to exit. Returns whether they did.
+    async fn wait_for_children(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Keep talking to the clients so they can clean up and close their windows
+            let dispatched = task::block_in_place::<_, Result<()>>(|| {
+                self.display.dispatch_clients(&mut self.state)?;
+                self.display.flush_clients()?;
+                Ok(())
+            });
+            if let Err(error) = dispatched {
+                println!("Failed to dispatch clients while shutting down: {error}");
+            }
+
+            if self.state.app_controller.all_exited().await {
+                return true;
             }
-            | Event::Move {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Ping clients that are due and let the app controller know about the ones that stopped (or started)
+    /// answering in time
+    async fn check_pings(&mut self) {
+        if self.ping.interval_ms == 0 {
+            return;
+        }
+        let interval = Duration::from_millis(self.ping.interval_ms);
+        let timeout = Duration::from_millis(self.ping.timeout_ms);
+
+        self.state.ping_states.retain(|_, ping| ping.client.alive());
+
+        let mut changed = Vec::new();
+        for (pid, ping) in self.state.ping_states.iter_mut() {
+            match ping.pending {
+                Some(sent) => {
+                    if ping.responsive && sent.elapsed() >= timeout {
+                        ping.responsive = false;
+                        changed.push((*pid, false));
+                    }
+                }
+                None => {
+                    if !ping.responsive {
+                        ping.responsive = true;
+                        changed.push((*pid, true));
+                    }
+                    if ping.last_ping.elapsed() >= interval {
+                        ping.last_ping = Instant::now();
+                        match ping.client.send_ping(SERIAL_COUNTER.next_serial()) {
+                            Ok(()) => ping.pending = Some(ping.last_ping),
+                            Err(error) => println!("Failed to ping {pid}: {error:?}"),
+                        }
+                    }
+                }
+            }
+        }
+
+        for (pid, responsive) in changed {
+            self.state
+                .app_controller
+                .set_responsive(pid, responsive)
+                .await;
+        }
+    }
+
+    /// Switch to the attract layout once nobody has interacted for [IdleConfig::timeout_ms]
+    fn check_idle(&mut self) {
+        let Some(idle) = &self.idle else {
+            return;
+        };
+        if self.is_idle || self.last_interaction.elapsed() < Duration::from_millis(idle.timeout_ms)
+        {
+            return;
+        }
+
+        self.is_idle = true;
+        self.pointer_grab = None;
+        let _ = self.idle_switches.send(true);
+    }
+
+    pub async fn send_pointer_button(&mut self, event: Event) {
+        self.last_interaction = Instant::now();
+        if self.is_idle {
+            // The touch that wakes the wall up only brings back the interactive layout. It isn't passed on
+            // since the app under it is about to go away.
+            self.is_idle = false;
+            let _ = self.idle_switches.send(false);
+            return;
+        }
+
+        let event = match event {
+            Event::Touch {
+                phase,
+                id,
                 pointer_x,
                 pointer_y,
-            } => {
-                let time = (self.start_time.elapsed().as_millis() % (u32::MAX as u128)) as u32;
-                let location = Point::from((pointer_x, pointer_y));
-                let event = MotionEvent {
-                    location,
-                    serial: SERIAL_COUNTER.next_serial(),
-                    time,
+            } => match self.touch_as_pointer(phase, id, pointer_x, pointer_y) {
+                Some(event) => event,
+                None => return,
+            },
+            event => event,
+        };
+
+        let time = (self.start_time.elapsed().as_millis() % (u32::MAX as u128)) as u32;
+
+        let (Event::Button {
+            pointer_x,
+            pointer_y,
+            ..
+        }
+        | Event::Move {
+            pointer_x,
+            pointer_y,
+        }) = event
+        else {
+            unreachable!("touches were turned into pointer events");
+        };
+        let canvas_location = Point::from((pointer_x, pointer_y));
+
+        // While a button is held everything goes to the surface it was pressed on, even once the pointer has
+        // left it. Otherwise a drag would switch apps half way through the gesture. Touches hold the left
+        // button down until the last one lifts, so a touch sequence stays with the surface it started on.
+        let (focus, mirror, location) = match &self.pointer_grab {
+            Some(grab) if grab.is_alive() => {
+                let location = match grab.mirror {
+                    Some(mirror) => self
+                        .state
+                        .mirror_input_location(mirror, canvas_location)
+                        .await
+                        .unwrap_or(canvas_location),
+                    None => canvas_location,
                 };
+                (grab.focus.clone(), grab.mirror, location)
+            }
+            _ => {
+                self.pointer_grab = None;
+                self.state.pointer_target(canvas_location).await
+            }
+        };
+
+        let motion = MotionEvent {
+            location,
+            serial: SERIAL_COUNTER.next_serial(),
+            time,
+        };
+        self.pointer.motion(&mut self.state, focus.clone(), &motion);
 
-                let focus = self
-                    .state
-                    .get_surface_at_pos(location)
-                    .await
-                    .map(|(a, b)| (a.clone(), b));
-                self.pointer.motion(&mut self.state, focus, &event);
+        let Event::Button { state, button, .. } = event else {
+            return;
+        };
+
+        match state {
+            ButtonState::Pressed => {
+                let grab = self.pointer_grab.get_or_insert_with(|| PointerGrab {
+                    focus,
+                    buttons: Vec::new(),
+                    mirror,
+                });
+                if !grab.buttons.contains(&button) {
+                    grab.buttons.push(button);
+                }
+            }
+            ButtonState::Released => {
+                if let Some(grab) = &mut self.pointer_grab {
+                    grab.buttons.retain(|pressed| *pressed != button);
+                }
             }
         }
 
-        if let Event::Button { state, button, .. } = event {
-            let event = ButtonEvent {
+        let event = ButtonEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time,
+            button,
+            state,
+        };
+        self.pointer.button(&mut self.state, &event);
+
+        // The grab is over once every button is up. Hand focus to whatever is now under the pointer.
+        if self
+            .pointer_grab
+            .as_ref()
+            .is_some_and(|grab| grab.buttons.is_empty())
+        {
+            self.pointer_grab = None;
+            let (focus, _, location) = self.state.pointer_target(canvas_location).await;
+            let motion = MotionEvent {
+                location,
                 serial: SERIAL_COUNTER.next_serial(),
                 time,
-                button,
-                state,
             };
-            self.pointer.button(&mut self.state, &event)
+            self.pointer.motion(&mut self.state, focus, &motion);
+        }
+    }
+
+    /// The pointer event a touch is passed on as, if any. The first touch point presses the left button and
+    /// moves the pointer. Touch points added after it are only kept track of, the button is released once
+    /// the last of them lifts.
+    fn touch_as_pointer(
+        &mut self,
+        phase: TouchPhase,
+        id: u32,
+        pointer_x: f64,
+        pointer_y: f64,
+    ) -> Option<Event> {
+        match phase {
+            TouchPhase::Down => {
+                let first = self.touches.is_empty();
+                if !self.touches.contains(&id) {
+                    self.touches.push(id);
+                }
+                first.then_some(Event::Button {
+                    state: ButtonState::Pressed,
+                    button: BTN_LEFT,
+                    pointer_x,
+                    pointer_y,
+                })
+            }
+            TouchPhase::Motion => (self.touches.first() == Some(&id)).then_some(Event::Move {
+                pointer_x,
+                pointer_y,
+            }),
+            TouchPhase::Up => {
+                let down = self.touches.contains(&id);
+                self.touches.retain(|touch| *touch != id);
+                (down && self.touches.is_empty()).then_some(Event::Button {
+                    state: ButtonState::Released,
+                    button: BTN_LEFT,
+                    pointer_x,
+                    pointer_y,
+                })
+            }
         }
     }
 }
 
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        // Anything still running would hold on to the dead wayland socket. Does nothing after
+        // [Compositor::shutdown].
+        self.state.app_controller.kill_all_now();
+    }
+}
+
+/// Implicit grab started by a button press. Keeps pointer focus on the pressed surface until all buttons
+/// are released.
+#[derive(Debug)]
+struct PointerGrab {
+    focus: Option<(WlSurface, Point<i32, Logical>)>,
+    buttons: Vec<u32>,
+    /// The mirror the grab started on. Input keeps being passed on through it.
+    mirror: Option<u32>,
+}
+
+impl PointerGrab {
+    /// A grab on a surface that has since been destroyed (e.g. the app crashed mid drag) is meaningless
+    fn is_alive(&self) -> bool {
+        self.focus
+            .as_ref()
+            .map_or(true, |(surface, _)| surface.is_alive())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Event {
     Button {
@@ -602,6 +1263,177 @@ pub enum Event {
         pointer_x: f64,
         pointer_y: f64,
     },
+    /// A finger on the wall. `id` tells apart fingers that are down at the same time.
+    Touch {
+        phase: TouchPhase,
+        id: u32,
+        pointer_x: f64,
+        pointer_y: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Down,
+    Motion,
+    Up,
+}
+
+render_elements! {
+    SwallRenderElement<=GlesRenderer>;
+    Surface=CropRenderElement<WaylandSurfaceRenderElement<GlesRenderer>>,
+    Mirror=MirrorRenderElement,
+    Border=SolidColorRenderElement,
+    Memory=MemoryRenderBufferRenderElement<GlesRenderer>,
+}
+
+/// The images of a [config::Background] as image tiles, numbered by screen
+fn background_tiles(
+    background: &config::Background,
+    canvas: config::Rect,
+    screens: &[config::Rect],
+) -> Vec<(u32, config::Rect, config::ImageTile)> {
+    let tile = |path: &PathBuf, fit: config::FitMode| config::ImageTile {
+        images: vec![config::SlideshowImage {
+            path: path.clone(),
+            duration_ms: None,
+        }],
+        fit,
+        duration_ms: 0,
+        crossfade_ms: 0,
+    };
+    match background {
+        config::Background::Color { .. } => Vec::new(),
+        config::Background::Image { path, fit, .. } => vec![(0, canvas, tile(path, *fit))],
+        config::Background::Screens { paths, fit, .. } => paths
+            .iter()
+            .zip(screens)
+            .enumerate()
+            .map(|(screen, (path, area))| (screen as u32, *area, tile(path, *fit)))
+            .collect(),
+    }
+}
+
+/// Elements for the border of `pid` around `content`. The buffers are kept in `buffers` between frames.
+fn border_elements(
+    buffers: &mut HashMap<u32, [SolidColorBuffer; 4]>,
+    pid: u32,
+    content: config::Rect,
+    border: config::Border,
+) -> Vec<SwallRenderElement> {
+    let buffers = buffers
+        .entry(pid)
+        .or_insert_with(|| std::array::from_fn(|_| SolidColorBuffer::new((0, 0), border.color)));
+    buffers
+        .iter_mut()
+        .zip(border_edges(content, border.width))
+        .map(|(buffer, edge)| {
+            buffer.update(edge.size, border.color);
+            SolidColorRenderElement::from_buffer(
+                buffer,
+                edge.loc.to_physical(1),
+                1.0,
+                1.0,
+                Kind::Unspecified,
+            )
+            .into()
+        })
+        .collect()
+}
+
+/// The four strips (top, bottom, left, right) of a border of `width` drawn just outside of `content`
+fn border_edges(content: config::Rect, width: u32) -> [Rectangle<i32, Logical>; 4] {
+    let (x, y) = (content.x as i32, content.y as i32);
+    let (w, h) = (content.width as i32, content.height as i32);
+    let b = width as i32;
+    [
+        Rectangle::from_loc_and_size((x - b, y - b), (w + 2 * b, b)),
+        Rectangle::from_loc_and_size((x - b, y + h), (w + 2 * b, b)),
+        Rectangle::from_loc_and_size((x - b, y), (b, h)),
+        Rectangle::from_loc_and_size((x + w, y), (b, h)),
+    ]
+}
+
+/// Window geometry set by the client with `xdg_surface.set_window_geometry`. This is the part of the surface
+/// the client considers to be the "window", excluding client side decorations like shadows.
+fn window_geometry(surface: &WlSurface) -> Option<Rectangle<i32, Logical>> {
+    smithay_compositor::with_states(surface, |states| {
+        states.cached_state.current::<SurfaceCachedState>().geometry
+    })
+}
+
+/// Where the surface tree has to be placed on the canvas so that its window geometry starts at the top left
+/// of `rect`.
+fn surface_origin(surface: &WlSurface, rect: config::Rect) -> Point<i32, Logical> {
+    let rect_origin = Point::from((rect.x as i32, rect.y as i32));
+    match window_geometry(surface) {
+        Some(geometry) => rect_origin - geometry.loc,
+        None => rect_origin,
+    }
+}
+
+/// Find the top most surface in the tree rooted at `surface` (placed at `origin`) that accepts input at
+/// `location`. Returns the surface along with where it is located on the canvas.
+fn surface_under(
+    surface: &WlSurface,
+    origin: Point<i32, Logical>,
+    location: Point<f64, Logical>,
+) -> Option<(WlSurface, Point<i32, Logical>)> {
+    let found = RefCell::new(None);
+
+    // Subsurfaces are positioned relative to their parent, so the location is accumulated down the tree
+    let subsurface_location = |states: &SurfaceData, parent: Point<i32, Logical>| {
+        if states.role == Some("subsurface") {
+            parent
+                + states
+                    .cached_state
+                    .current::<SubsurfaceCachedState>()
+                    .location
+        } else {
+            parent
+        }
+    };
+
+    // Downward visits the surfaces closest to the viewer first, so the first hit is the one on top
+    with_surface_tree_downward(
+        surface,
+        origin,
+        |_, states, parent| TraversalAction::DoChildren(subsurface_location(states, *parent)),
+        |surface, states, parent| {
+            let surface_location = subsurface_location(states, *parent);
+            if accepts_input(states, location - surface_location.to_f64()) {
+                *found.borrow_mut() = Some((surface.clone(), surface_location));
+            }
+        },
+        |_, _, _| found.borrow().is_none(),
+    );
+
+    found.into_inner()
+}
+
+/// Whether a point local to a surface is on the surface and inside its input region
+fn accepts_input(states: &SurfaceData, local: Point<f64, Logical>) -> bool {
+    let Some(size) = states
+        .data_map
+        .get::<RendererSurfaceStateUserData>()
+        .and_then(|data| data.borrow().surface_size())
+    else {
+        // Nothing has been committed yet so there is nothing to click on
+        return false;
+    };
+
+    let local = local.to_i32_floor();
+    if !Rectangle::from_loc_and_size((0, 0), size).contains(local) {
+        return false;
+    }
+
+    // No input region means the whole surface accepts input
+    states
+        .cached_state
+        .current::<SurfaceAttributes>()
+        .input_region
+        .as_ref()
+        .map_or(true, |region| region.contains(local))
 }
 
 pub fn send_frames_surface_tree(surface: &wl_surface::WlSurface, time: u32) {
@@ -645,8 +1477,9 @@ impl ClientData for ClientState {
         println!("initialized");
     }
 
-    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {
-        println!("disconnected");
+    fn disconnected(&self, _client_id: ClientId, reason: DisconnectReason) {
+        // The surfaces are cleaned up by smithay. If the process exited it gets reaped by the app controller.
+        println!("Client disconnected: {reason:?} (pid: {})", self.client_pid);
     }
 }
 
@@ -656,3 +1489,4 @@ mod custom_xdg_shell_impl;
 delegate_compositor!(App);
 delegate_shm!(App);
 delegate_seat!(App);
+delegate_xdg_decoration!(App);
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use smithay::{
+    backend::renderer::{
+        element::{
+            surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+            utils::{CropRenderElement, RescaleRenderElement},
+            Element, Id, Kind, RenderElement,
+        },
+        gles::{GlesError, GlesRenderer},
+        utils::CommitCounter,
+        Renderer,
+    },
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Transform},
+    wayland::compositor::with_states,
+};
+
+use crate::config::{Mirror, MirrorInput, Rect};
+use crate::layer_shell::UPPER_LAYERS;
+use crate::{surface_origin, surface_under, App, SurfacePid, SwallRenderElement};
+
+/// A window element drawn again in a mirror. It has an id of its own since the damage tracker keeps track of
+/// elements by id, and would mix the mirror up with the window (or another mirror of it) otherwise.
+#[derive(Debug)]
+pub(crate) struct MirrorRenderElement {
+    id: Id,
+    element: CropRenderElement<RescaleRenderElement<WaylandSurfaceRenderElement<GlesRenderer>>>,
+}
+
+impl Element for MirrorRenderElement {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.element.current_commit()
+    }
+
+    fn location(&self, scale: Scale<f64>) -> Point<i32, Physical> {
+        self.element.location(scale)
+    }
+
+    fn src(&self) -> Rectangle<f64, Buffer> {
+        self.element.src()
+    }
+
+    fn transform(&self) -> Transform {
+        self.element.transform()
+    }
+
+    fn geometry(&self, scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.element.geometry(scale)
+    }
+
+    fn damage_since(
+        &self,
+        scale: Scale<f64>,
+        commit: Option<CommitCounter>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        self.element.damage_since(scale, commit)
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> Vec<Rectangle<i32, Physical>> {
+        self.element.opaque_regions(scale)
+    }
+
+    fn alpha(&self) -> f32 {
+        self.element.alpha()
+    }
+
+    fn kind(&self) -> Kind {
+        self.element.kind()
+    }
+}
+
+impl RenderElement<GlesRenderer> for MirrorRenderElement {
+    fn draw(
+        &self,
+        frame: &mut <GlesRenderer as Renderer>::Frame<'_>,
+        src: Rectangle<f64, Buffer>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), GlesError> {
+        RenderElement::<GlesRenderer>::draw(&self.element, frame, src, dst, damage)
+    }
+}
+
+/// Ids of the elements of every mirror, by mirror and the id of the window element they copy. Ids that
+/// weren't used for a frame are dropped.
+#[derive(Debug, Default)]
+pub(crate) struct MirrorIds {
+    previous: HashMap<(u32, Id), Id>,
+    current: HashMap<(u32, Id), Id>,
+}
+
+impl MirrorIds {
+    fn get(&mut self, mirror: u32, of: &Id) -> Id {
+        let key = (mirror, of.clone());
+        if let Some(id) = self.current.get(&key) {
+            return id.clone();
+        }
+        let id = self.previous.remove(&key).unwrap_or_else(Id::new);
+        self.current.insert(key, id.clone());
+        id
+    }
+
+    /// Forget the ids that weren't used since the last call
+    pub(crate) fn end_frame(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+}
+
+/// Render elements for the window `surface` drawn again in mirror `id`. `source_area` is where the window is
+/// normally drawn.
+pub(crate) fn mirror_elements(
+    renderer: &mut GlesRenderer,
+    ids: &mut MirrorIds,
+    surface: &WlSurface,
+    id: u32,
+    mirror: &Mirror,
+    source_area: Rect,
+) -> Vec<SwallRenderElement> {
+    let mirror_origin: Point<i32, Physical> = (mirror.rect.x as i32, mirror.rect.y as i32).into();
+    let offset = surface_origin(surface, source_area)
+        - Point::from((source_area.x as i32, source_area.y as i32));
+    let crop: Rectangle<i32, Physical> = Rectangle::from_loc_and_size(
+        mirror_origin,
+        (mirror.rect.width as i32, mirror.rect.height as i32),
+    );
+    let scale = mirror_scale(mirror, source_area);
+
+    // The window is laid out at its own size from the corner of the mirror, then scaled around that corner
+    let elements: Vec<WaylandSurfaceRenderElement<GlesRenderer>> =
+        render_elements_from_surface_tree(
+            renderer,
+            surface,
+            mirror_origin + offset.to_physical(1),
+            1.0,
+            1.0,
+            Kind::Unspecified,
+        );
+    elements
+        .into_iter()
+        .map(|e| RescaleRenderElement::from_element(e, mirror_origin, scale))
+        .filter_map(|e| CropRenderElement::from_element(e, 1.0, crop))
+        .map(|element| {
+            SwallRenderElement::from(MirrorRenderElement {
+                id: ids.get(id, element.id()),
+                element,
+            })
+        })
+        .collect()
+}
+
+/// How much bigger a mirror is than its source, along each axis
+fn mirror_scale(mirror: &Mirror, source_area: Rect) -> Scale<f64> {
+    Scale::from((
+        mirror.rect.width as f64 / source_area.width.max(1) as f64,
+        mirror.rect.height as f64 / source_area.height.max(1) as f64,
+    ))
+}
+
+impl App {
+    /// Where pointer input at `location` on the canvas goes. Returns the surface under it, the mirror it's on
+    /// and the location as far as the surface is concerned. Input on a mirror goes to the same spot on the
+    /// mirrored application, or nowhere if the mirror doesn't forward input.
+    pub(crate) async fn pointer_target(
+        &self,
+        location: Point<f64, Logical>,
+    ) -> (
+        Option<(WlSurface, Point<i32, Logical>)>,
+        Option<u32>,
+        Point<f64, Logical>,
+    ) {
+        let Some(mirror) = self.mirror_under(location).await else {
+            return (self.get_surface_at_pos(location).await, None, location);
+        };
+        match self.mirror_input_location(mirror, location).await {
+            Some(source_location) => (
+                self.mirror_source_surface_at(mirror, source_location).await,
+                Some(mirror),
+                source_location,
+            ),
+            // The mirror covers whatever is under it, so that doesn't get the input either
+            None => (None, Some(mirror), location),
+        }
+    }
+
+    /// The top most mirror at `location`. Layer surfaces above the applications are drawn over mirrors as
+    /// well, so input on them isn't a mirror's.
+    async fn mirror_under(&self, location: Point<f64, Logical>) -> Option<u32> {
+        if location.x < 0.0
+            || location.y < 0.0
+            || self.layer_surface_under(&UPPER_LAYERS, location).is_some()
+        {
+            return None;
+        }
+        self.application_viewer
+            .snapshot()
+            .await
+            .mirrors()
+            .into_iter()
+            .find(|(_, mirror, _)| mirror.rect.is_inside(location.x as u32, location.y as u32))
+            .map(|(id, ..)| id)
+    }
+
+    /// The window of mirror `id`'s source at `location`, which is on the source. Only the source's own windows
+    /// are looked at, whatever is drawn over the source doesn't show in the mirror.
+    async fn mirror_source_surface_at(
+        &self,
+        id: u32,
+        location: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let (mirror, source_area) = self.application_viewer.snapshot().await.mirror(id)?;
+        if !source_area.is_inside(location.x as u32, location.y as u32) {
+            return None;
+        }
+        self.xdg_shell_state
+            .toplevel_surfaces()
+            .iter()
+            .rev()
+            .find_map(|toplevel| {
+                let surface = toplevel.wl_surface();
+                let pid = with_states(surface, |states| {
+                    states.data_map.get::<SurfacePid>().copied()
+                });
+                if pid != Some(SurfacePid(mirror.source_pid)) {
+                    return None;
+                }
+                surface_under(surface, surface_origin(surface, source_area), location)
+            })
+    }
+
+    /// Where input at `location` on mirror `id` lands on its source. `None` if the mirror doesn't forward
+    /// input or its source isn't showing.
+    pub(crate) async fn mirror_input_location(
+        &self,
+        id: u32,
+        location: Point<f64, Logical>,
+    ) -> Option<Point<f64, Logical>> {
+        let (mirror, source_area) = self.application_viewer.snapshot().await.mirror(id)?;
+        if mirror.input != MirrorInput::Forward {
+            return None;
+        }
+        let scale = mirror_scale(&mirror, source_area);
+        Some(Point::from((
+            source_area.x as f64 + (location.x - mirror.rect.x as f64) / scale.x,
+            source_area.y as f64 + (location.y - mirror.rect.y as f64) / scale.y,
+        )))
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use anyhow::Result;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                Kind,
+            },
+            gles::GlesRenderer,
+        },
+    },
+    utils::Transform,
+};
+use tokio::task::JoinHandle;
+
+use crate::config::{OverlayWidget, WidgetKind};
+use crate::raster::{self, Raster};
+
+/// Draws the overlay widgets. Widgets are rasterized off the render loop and kept until they change, so
+/// a widget that hasn't changed costs nothing to draw.
+#[derive(Debug, Default)]
+pub struct OverlayRenderer {
+    widgets: HashMap<String, RenderedWidget>,
+}
+
+#[derive(Debug, Default)]
+struct RenderedWidget {
+    /// What was last asked to be rasterized
+    requested: Option<Content>,
+    pending: Option<JoinHandle<Result<Raster>>>,
+    buffer: Option<MemoryRenderBuffer>,
+}
+
+/// Everything that decides what a widget looks like
+#[derive(Debug, Clone, PartialEq)]
+struct Content {
+    kind: WidgetKind,
+    width: u32,
+    height: u32,
+    /// What a clock is currently showing
+    time: Option<String>,
+}
+
+impl Content {
+    fn of(widget: &OverlayWidget) -> Self {
+        Self {
+            kind: widget.kind.clone(),
+            width: widget.area.width,
+            height: widget.area.height,
+            time: match &widget.kind {
+                WidgetKind::Clock { format, .. } => Some(local_time(format)),
+                _ => None,
+            },
+        }
+    }
+
+    fn rasterize(&self) -> Result<Raster> {
+        match &self.kind {
+            WidgetKind::Text {
+                text,
+                style,
+                background,
+            } => raster::render_text(text, style, *background, self.width, self.height),
+            WidgetKind::Clock {
+                style, background, ..
+            } => raster::render_text(
+                self.time.as_deref().unwrap_or_default(),
+                style,
+                *background,
+                self.width,
+                self.height,
+            ),
+            WidgetKind::Image { path } => raster::load_image(path, self.width, self.height),
+        }
+    }
+}
+
+impl OverlayRenderer {
+    /// Render elements for `widgets`, front to back. Widgets that haven't been rasterized yet are left out
+    /// until they are.
+    pub async fn elements(
+        &mut self,
+        renderer: &mut GlesRenderer,
+        widgets: &[OverlayWidget],
+    ) -> Result<Vec<MemoryRenderBufferRenderElement<GlesRenderer>>> {
+        self.widgets
+            .retain(|id, _| widgets.iter().any(|widget| &widget.id == id));
+
+        let mut elements = Vec::with_capacity(widgets.len());
+        for widget in widgets {
+            let rendered = self.widgets.entry(widget.id.clone()).or_default();
+
+            let finished = rendered
+                .pending
+                .as_ref()
+                .is_some_and(JoinHandle::is_finished);
+            if let Some(pending) = finished.then(|| rendered.pending.take()).flatten() {
+                match pending.await {
+                    Ok(Ok(raster)) => {
+                        rendered.buffer = Some(MemoryRenderBuffer::from_slice(
+                            &raster.rgba,
+                            Fourcc::Abgr8888,
+                            (raster.width as i32, raster.height as i32),
+                            1,
+                            Transform::Normal,
+                            None,
+                        ))
+                    }
+                    Ok(Err(error)) => {
+                        println!("Can't draw overlay widget '{}': {error}", widget.id)
+                    }
+                    // A widget that crashes its rasterizer is left out rather than taking down the whole wall.
+                    // It's tried again once it changes.
+                    Err(error) => {
+                        println!(
+                            "Drawing overlay widget '{}' failed: {error}. Leaving it out.",
+                            widget.id
+                        );
+                        rendered.buffer = None;
+                    }
+                }
+            }
+
+            let content = Content::of(widget);
+            if rendered.pending.is_none() && rendered.requested.as_ref() != Some(&content) {
+                rendered.requested = Some(content.clone());
+                rendered.pending = Some(tokio::task::spawn_blocking(move || content.rasterize()));
+            }
+
+            if let Some(buffer) = &rendered.buffer {
+                elements.push(MemoryRenderBufferRenderElement::from_buffer(
+                    renderer,
+                    (widget.area.x as f64, widget.area.y as f64),
+                    buffer,
+                    None,
+                    None,
+                    None,
+                    Kind::Unspecified,
+                )?);
+            }
+        }
+
+        // Later widgets are drawn over earlier ones
+        elements.reverse();
+        Ok(elements)
+    }
+}
+
+/// The current local time formatted with `strftime`
+fn local_time(format: &str) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let mut buffer = [0u8; 256];
+    // SAFETY: localtime_r only writes to the tm it's given and strftime writes at most `buffer.len()` bytes
+    let written = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        libc::strftime(
+            buffer.as_mut_ptr().cast(),
+            buffer.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buffer[..written]).into_owned()
+}
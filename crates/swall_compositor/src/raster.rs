@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use gstreamer::prelude::*;
+use gstreamer::{ClockTime, MessageType, MessageView};
+
+use crate::config::{TextAlign, TextStyle};
+
+/// How long decoding an image or drawing text may take before giving up
+const RASTER_TIMEOUT: ClockTime = ClockTime::from_seconds(10);
+
+/// Pixels drawn by the compositor itself rather than by an application
+#[derive(Debug, Clone)]
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGBA, row by row
+    pub rgba: Vec<u8>,
+}
+
+/// Decode a PNG or JPEG file and stretch it to `width` by `height`
+pub fn load_image(path: &Path, width: u32, height: u32) -> Result<Raster> {
+    decode(
+        path,
+        &format!("width={width},height={height},pixel-aspect-ratio=1/1"),
+    )
+}
+
+/// Decode a PNG or JPEG file at its own size
+pub fn decode_image(path: &Path) -> Result<Raster> {
+    decode(path, "pixel-aspect-ratio=1/1")
+}
+
+fn decode(path: &Path, caps: &str) -> Result<Raster> {
+    let pipeline = build(&format!(
+        "filesrc name=src ! decodebin ! videoconvert ! videoscale add-borders=false ! \
+         video/x-raw,format=RGBA,{caps} ! fakesink name=sink enable-last-sample=true sync=false"
+    ))?;
+    element(&pipeline, "src")?.set_property("location", path.to_string_lossy().to_string());
+    pull_frame(&pipeline).map_err(|error| anyhow!("Can't load '{}': {error}", path.display()))
+}
+
+/// Draw `text` into a `width` by `height` area filled with `background` (transparent if not set)
+pub fn render_text(
+    text: &str,
+    style: &TextStyle,
+    background: Option<[f32; 4]>,
+    width: u32,
+    height: u32,
+) -> Result<Raster> {
+    // The text is drawn onto a single frame of solid colour
+    let pipeline = build(&format!(
+        "videotestsrc name=src num-buffers=1 pattern=solid-color ! \
+         video/x-raw,format=BGRA,width={width},height={height} ! \
+         textoverlay name=text auto-resize=false draw-shadow=false valignment=center ! \
+         videoconvert ! video/x-raw,format=RGBA ! fakesink name=sink enable-last-sample=true sync=false"
+    ))?;
+    element(&pipeline, "src")?
+        .set_property("foreground-color", argb(background.unwrap_or([0.0; 4])));
+    let overlay = element(&pipeline, "text")?;
+    overlay.set_property("text", text);
+    overlay.set_property("font-desc", style.font.as_str());
+    overlay.set_property("color", argb(style.color));
+    overlay.set_property_from_str(
+        "halignment",
+        match style.align {
+            TextAlign::Left => "left",
+            TextAlign::Center => "center",
+            TextAlign::Right => "right",
+        },
+    );
+    pull_frame(&pipeline)
+}
+
+fn build(description: &str) -> Result<gstreamer::Pipeline> {
+    gstreamer::init()?;
+    gstreamer::parse::launch(description)?
+        .downcast::<gstreamer::Pipeline>()
+        .map_err(|_| anyhow!("'{description}' is not a pipeline"))
+}
+
+fn element(pipeline: &gstreamer::Pipeline, name: &str) -> Result<gstreamer::Element> {
+    pipeline
+        .by_name(name)
+        .ok_or_else(|| anyhow!("Pipeline has no element named '{name}'"))
+}
+
+/// Run the pipeline until it's done and take the last frame that reached the sink
+fn pull_frame(pipeline: &gstreamer::Pipeline) -> Result<Raster> {
+    let sink = element(pipeline, "sink")?;
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| anyhow!("Pipeline has no bus"))?;
+
+    pipeline.set_state(gstreamer::State::Playing)?;
+    let message = bus.timed_pop_filtered(RASTER_TIMEOUT, &[MessageType::Eos, MessageType::Error]);
+    let sample = sink.property::<Option<gstreamer::Sample>>("last-sample");
+    pipeline.set_state(gstreamer::State::Null)?;
+
+    match message {
+        Some(message) => {
+            if let MessageView::Error(error) = message.view() {
+                bail!("{}", error.error());
+            }
+        }
+        None => bail!("Timed out after {RASTER_TIMEOUT}"),
+    }
+
+    let sample = sample.ok_or_else(|| anyhow!("No frame was produced"))?;
+    let structure = sample
+        .caps()
+        .and_then(|caps| caps.structure(0))
+        .ok_or_else(|| anyhow!("Frame has no caps"))?;
+    let width = structure.get::<i32>("width")? as u32;
+    let height = structure.get::<i32>("height")? as u32;
+    let buffer = sample
+        .buffer()
+        .ok_or_else(|| anyhow!("Frame has no buffer"))?
+        .map_readable()?;
+
+    Ok(Raster {
+        width,
+        height,
+        rgba: buffer.as_slice().to_vec(),
+    })
+}
+
+/// Pack an RGBA colour with channels between 0 and 1 into the `0xAARRGGBB` GStreamer elements take
+fn argb(color: [f32; 4]) -> u32 {
+    let [r, g, b, a] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u32);
+    a << 24 | r << 16 | g << 8 | b
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                Kind,
+            },
+            gles::GlesRenderer,
+        },
+    },
+    utils::Transform,
+};
+
+use crate::config::Rect;
+
+/// Video streams fed into the compositor from outside of Wayland, such as the request sink pads of the
+/// GStreamer element. Each stream is drawn in its own rect. Safe to use from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct VideoInputs(Arc<Mutex<HashMap<u32, VideoInput>>>);
+
+#[derive(Debug, Clone)]
+struct VideoInput {
+    rect: Rect,
+    frame: VideoFrame,
+    /// Counts up with every frame so unchanged frames aren't uploaded again
+    serial: u64,
+}
+
+/// A single frame of packed RGBA video
+#[derive(Debug, Clone)]
+pub struct VideoFrame {
+    pub width: u32,
+    pub height: u32,
+    pub buffer: gstreamer::Buffer,
+}
+
+impl VideoInputs {
+    /// Show `frame` as the latest frame of stream `id`, stretched over `rect`. The rect comes with every
+    /// frame so a stream can be moved while it's playing.
+    pub fn push_frame(&self, id: u32, rect: Rect, frame: VideoFrame) {
+        let mut inputs = self.0.lock().unwrap();
+        let serial = inputs.get(&id).map_or(0, |input| input.serial + 1);
+        inputs.insert(
+            id,
+            VideoInput {
+                rect,
+                frame,
+                serial,
+            },
+        );
+    }
+
+    /// Stop drawing stream `id`
+    pub fn remove(&self, id: u32) {
+        self.0.lock().unwrap().remove(&id);
+    }
+
+    fn snapshot(&self) -> Vec<(u32, VideoInput)> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, input)| (*id, input.clone()))
+            .collect()
+    }
+}
+
+/// Uploads the latest frame of each of the [VideoInputs] when it changes
+#[derive(Debug, Default)]
+pub(crate) struct VideoRenderer {
+    buffers: HashMap<u32, (u64, MemoryRenderBuffer)>,
+}
+
+impl VideoRenderer {
+    /// Render elements for every stream. Streams are kept apart by their rects so the order doesn't matter.
+    pub(crate) fn elements(
+        &mut self,
+        renderer: &mut GlesRenderer,
+        inputs: &VideoInputs,
+    ) -> Result<Vec<MemoryRenderBufferRenderElement<GlesRenderer>>> {
+        let inputs = inputs.snapshot();
+        self.buffers
+            .retain(|id, _| inputs.iter().any(|(input_id, _)| input_id == id));
+
+        let mut elements = Vec::with_capacity(inputs.len());
+        for (id, input) in inputs {
+            let up_to_date = self
+                .buffers
+                .get(&id)
+                .is_some_and(|(serial, _)| *serial == input.serial);
+            if !up_to_date {
+                let frame = &input.frame;
+                let map = frame.buffer.map_readable()?;
+                let length = frame.width as usize * frame.height as usize * 4;
+                // A bad frame from one stream shouldn't take down the rest of the wall
+                let Some(pixels) = map.as_slice().get(..length) else {
+                    println!(
+                        "Frame of video stream {id} is too small for {}x{} RGBA. Skipping it.",
+                        frame.width, frame.height
+                    );
+                    continue;
+                };
+                let buffer = MemoryRenderBuffer::from_slice(
+                    pixels,
+                    Fourcc::Abgr8888,
+                    (frame.width as i32, frame.height as i32),
+                    1,
+                    Transform::Normal,
+                    None,
+                );
+                self.buffers.insert(id, (input.serial, buffer));
+            }
+
+            let (_, buffer) = &self.buffers[&id];
+            elements.push(MemoryRenderBufferRenderElement::from_buffer(
+                renderer,
+                (input.rect.x as f64, input.rect.y as f64),
+                buffer,
+                None,
+                None,
+                Some((input.rect.width as i32, input.rect.height as i32).into()),
+                Kind::Unspecified,
+            )?);
+        }
+        Ok(elements)
+    }
+}
@@ -2,13 +2,21 @@
 use gstreamer::glib;
 
 pub(crate) mod translate_event;
+pub(crate) mod video_pad;
 
 mod imp {
     use gstreamer::glib::subclass::object::ObjectImplExt;
-    use gstreamer::glib::subclass::types::ObjectSubclassExt;
-    use gstreamer::subclass::prelude::{ElementImpl, GstObjectImpl, ObjectImpl, ObjectSubclass};
+    use gstreamer::glib::subclass::types::{ObjectSubclassExt, ObjectSubclassIsExt};
+    use gstreamer::glib::StaticType;
+    use gstreamer::prelude::{Cast, ClockExt, ElementExt, PadExt};
+    use gstreamer::subclass::prelude::{
+        ElementImpl, ElementImplExt, GstObjectImpl, ObjectImpl, ObjectSubclass,
+    };
     use gstreamer::subclass::ElementMetadata;
-    use gstreamer::{glib, Buffer, Caps, Event, EventType, PadTemplate};
+    use gstreamer::{
+        glib, Buffer, Caps, ClockError, ClockTime, Event, EventType, EventView, FlowError,
+        FlowSuccess, FormattedSegment, Pad, PadBuilder, PadTemplate,
+    };
     use gstreamer_base::prelude::BaseSrcExt;
     use gstreamer_base::subclass::base_src::{BaseSrcImpl, CreateSuccess};
     use gstreamer_base::subclass::prelude::PushSrcImpl;
@@ -18,12 +26,15 @@ mod imp {
     use std::path::Path;
     use std::sync::{Arc, Mutex};
     use std::thread;
-    use swall_compositor::{config::CompositorConfig, Compositor, HARDCODED_COMPOSITOR_SIZE};
+    use swall_compositor::config::{CompositorConfig, Rect};
+    use swall_compositor::video::{VideoFrame, VideoInputs};
+    use swall_compositor::{Compositor, HARDCODED_COMPOSITOR_SIZE};
     use tokio::sync::mpsc;
     use tokio::sync::mpsc::error::TryRecvError;
     use tokio::task::LocalSet;
 
     use crate::translate_event::translate_event;
+    use crate::video_pad::{self, VideoSinkPad};
 
     /// Capturing the navigation events
     #[derive(Debug)]
@@ -31,6 +42,10 @@ mod imp {
         tokio_rt: Arc<tokio::runtime::Runtime>,
         config: Arc<CompositorConfig>,
         compositor_notifiers: Mutex<Option<(mpsc::Receiver<Buffer>, mpsc::Sender<Event>)>>,
+        /// Latest frames of the streams coming in through the request sink pads
+        video_inputs: VideoInputs,
+        /// Number given to the next requested sink pad that isn't given a name
+        next_pad_id: Mutex<u32>,
     }
 
     #[glib::object_subclass]
@@ -67,6 +82,8 @@ mod imp {
                 tokio_rt,
                 config: config.into(),
                 compositor_notifiers: Mutex::new(None),
+                video_inputs: VideoInputs::default(),
+                next_pad_id: Mutex::new(0),
             }
         }
     }
@@ -114,6 +131,7 @@ mod imp {
             let (event_sender, mut event_receiver) = mpsc::channel::<Event>(4);
 
             let config = self.config.clone();
+            let video_inputs = self.video_inputs.clone();
             let rt = self.tokio_rt.clone();
             // Spawn off a thread generating compositor frames
             // The compositor is not [Send] so in order to make it async we need to tell the executor
@@ -124,6 +142,7 @@ mod imp {
                 local.spawn_local(async move {
                     // TODO: Better Error Handling
                     let mut compositor = Compositor::new(config).await.unwrap();
+                    compositor.set_video_inputs(video_inputs);
 
                     'compositor_loop: loop {
 
@@ -189,6 +208,9 @@ mod imp {
                         let buffer = compositor.generate_frame().await.unwrap();
                         frame_slot.send(buffer);
                     }
+
+                    // The element was stopped. Don't leave the apps running without a compositor.
+                    compositor.shutdown().await;
                 });
 
                 rt.block_on(local); // Run the task on this thread
@@ -234,11 +256,165 @@ mod imp {
                 )
                 .unwrap();
 
-                vec![src_pad]
+                let video_caps = Caps::builder("video/x-raw").field("format", "RGBA").build();
+                let video_sink_pad = PadTemplate::with_gtype(
+                    VideoSinkPad::TEMPLATE_NAME,
+                    gstreamer::PadDirection::Sink,
+                    gstreamer::PadPresence::Request,
+                    &video_caps,
+                    VideoSinkPad::static_type(),
+                )
+                .unwrap();
+
+                vec![src_pad, video_sink_pad]
             });
 
             PAD_TEMPLATES.as_ref()
         }
+
+        fn request_new_pad(
+            &self,
+            templ: &PadTemplate,
+            name: Option<&str>,
+            _caps: Option<&Caps>,
+        ) -> Option<Pad> {
+            let id = {
+                let mut next_pad_id = self.next_pad_id.lock().unwrap();
+                let id = match name {
+                    Some(name) => video_pad::parse_id(name)?,
+                    None => *next_pad_id,
+                };
+                *next_pad_id = (*next_pad_id).max(id.checked_add(1)?);
+                id
+            };
+
+            let pad = PadBuilder::<VideoSinkPad>::from_template(templ)
+                .name(format!("sink_{id}"))
+                .chain_function(|pad, parent, buffer| {
+                    CompositorRoot::catch_panic_pad_function(
+                        parent,
+                        || Err(FlowError::Error),
+                        |this| this.video_chain(pad, buffer),
+                    )
+                })
+                .event_function(|pad, parent, event| {
+                    CompositorRoot::catch_panic_pad_function(
+                        parent,
+                        || false,
+                        |this| this.video_event(pad, event),
+                    )
+                })
+                .build();
+            // Fails if a pad with the same number was already requested
+            self.obj().add_pad(&pad).ok()?;
+            Some(pad.upcast())
+        }
+
+        fn release_pad(&self, pad: &Pad) {
+            if let Some(pad) = pad.downcast_ref::<VideoSinkPad>() {
+                pad.start_flush();
+                self.video_inputs.remove(pad.id());
+            }
+            let _ = pad.set_active(false);
+            let _ = self.obj().remove_pad(pad);
+        }
+    }
+
+    impl CompositorRoot {
+        /// Hand a frame from a request sink pad to the compositor once it's due
+        fn video_chain(
+            &self,
+            pad: &VideoSinkPad,
+            buffer: Buffer,
+        ) -> Result<FlowSuccess, FlowError> {
+            let (rect, size, running_time) = {
+                let settings = pad.imp().settings.lock().unwrap();
+                if settings.flushing {
+                    return Err(FlowError::Flushing);
+                }
+                (
+                    settings.rect,
+                    settings.size,
+                    settings.segment.to_running_time(buffer.pts()),
+                )
+            };
+            let Some((width, height)) = size else {
+                return Err(FlowError::NotNegotiated);
+            };
+
+            // Streams that aren't live, like files, would otherwise play as fast as they can be decoded
+            let element = self.obj();
+            if let (Some(clock), Some(base_time), Some(running_time)) =
+                (element.clock(), element.base_time(), running_time)
+            {
+                let clock_id = clock.new_single_shot_id(base_time + running_time);
+                {
+                    let mut settings = pad.imp().settings.lock().unwrap();
+                    // A flush may have started since the settings were last looked at
+                    if settings.flushing {
+                        return Err(FlowError::Flushing);
+                    }
+                    settings.clock_id = Some(clock_id.clone());
+                }
+                let (result, _) = clock_id.wait();
+                pad.imp().settings.lock().unwrap().clock_id = None;
+                if result == Err(ClockError::Unscheduled) {
+                    return Err(FlowError::Flushing);
+                }
+            }
+
+            let rect = Rect {
+                width: if rect.width == 0 { width } else { rect.width },
+                height: if rect.height == 0 {
+                    height
+                } else {
+                    rect.height
+                },
+                ..rect
+            };
+            self.video_inputs.push_frame(
+                pad.id(),
+                rect,
+                VideoFrame {
+                    width,
+                    height,
+                    buffer,
+                },
+            );
+            Ok(FlowSuccess::Ok)
+        }
+
+        /// Keep track of the format and timing of a request sink pad's stream. None of its events go
+        /// downstream, downstream only sees the composited video.
+        fn video_event(&self, pad: &VideoSinkPad, event: Event) -> bool {
+            match event.view() {
+                EventView::Caps(caps) => {
+                    let size = caps.caps().structure(0).and_then(|structure| {
+                        Some((
+                            structure.get::<i32>("width").ok()? as u32,
+                            structure.get::<i32>("height").ok()? as u32,
+                        ))
+                    });
+                    pad.imp().settings.lock().unwrap().size = size;
+                }
+                EventView::Segment(segment) => {
+                    if let Some(segment) = segment.segment().downcast_ref::<ClockTime>() {
+                        pad.imp().settings.lock().unwrap().segment = segment.clone();
+                    }
+                }
+                EventView::FlushStart(_) => pad.start_flush(),
+                EventView::FlushStop(_) => {
+                    let mut settings = pad.imp().settings.lock().unwrap();
+                    settings.flushing = false;
+                    // Timestamps start over after a flush until a new segment says otherwise
+                    settings.segment = FormattedSegment::new();
+                }
+                // The last frame of a finished stream isn't left up
+                EventView::Eos(_) => self.video_inputs.remove(pad.id()),
+                _ => {}
+            }
+            true
+        }
     }
 
     impl GstObjectImpl for CompositorRoot {}
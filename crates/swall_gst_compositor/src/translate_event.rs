@@ -1,5 +1,5 @@
 use gstreamer::{glib, Event, StructureRef};
-use swall_compositor::ButtonState;
+use swall_compositor::{ButtonState, TouchPhase};
 
 #[derive(Debug, thiserror::Error)]
 pub enum EventTranslationError {
@@ -30,33 +30,39 @@ where
 pub fn translate_event(event: Event) -> Result<swall_compositor::Event, EventTranslationError> {
     let event_contents = event.structure().unwrap();
     match extract_value(event_contents, "event")? {
-        "mouse-move" | "touch-motion" => Ok(swall_compositor::Event::Move {
+        "mouse-move" => Ok(swall_compositor::Event::Move {
             pointer_x: extract_value(event_contents, "pointer_x")?,
             pointer_y: extract_value(event_contents, "pointer_y")?,
         }),
-        press_raw @ ("mouse-button-press" | "mouse-button-release" | "touch-down" | "touch-up") => {
+        touch_raw @ ("touch-down" | "touch-motion" | "touch-up") => {
+            Ok(swall_compositor::Event::Touch {
+                phase: match touch_raw {
+                    "touch-down" => TouchPhase::Down,
+                    "touch-motion" => TouchPhase::Motion,
+                    "touch-up" => TouchPhase::Up,
+                    _ => unreachable!(),
+                },
+                id: extract_value(event_contents, "identifier")?,
+                pointer_x: extract_value(event_contents, "pointer_x")?,
+                pointer_y: extract_value(event_contents, "pointer_y")?,
+            })
+        }
+        press_raw @ ("mouse-button-press" | "mouse-button-release") => {
             let state = match press_raw {
-                "mouse-button-press" | "touch-down" => ButtonState::Pressed,
-                "mouse-button-release" | "touch-up" => ButtonState::Released,
+                "mouse-button-press" => ButtonState::Pressed,
+                "mouse-button-release" => ButtonState::Released,
                 _ => unreachable!(),
             };
 
-            let button = match press_raw {
-                "mouse-button-release" | "mouse-button-press" => {
-                    let button: i32 = extract_value(event_contents, "button")?;
-
-                    match button {
-                        1 => 0x110, // TODO: Unhardcode with smithay_client_toolkit::seat::pointer::BTN_LEFT
-                        3 => 0x111,
-                        // 2 => 0x111,
-                        _ => {
-                            println!("Unknown button type: {event:#?}");
-                            return Err(EventTranslationError::UnknownButtonType(button));
-                        }
-                    }
+            let button: i32 = extract_value(event_contents, "button")?;
+            let button = match button {
+                1 => 0x110, // TODO: Unhardcode with smithay_client_toolkit::seat::pointer::BTN_LEFT
+                3 => 0x111,
+                // 2 => 0x111,
+                _ => {
+                    println!("Unknown button type: {event:#?}");
+                    return Err(EventTranslationError::UnknownButtonType(button));
                 }
-                "touch-down" | "touch-up" => 0x110, // Left click
-                _ => unreachable!(),
             };
 
             Ok(swall_compositor::Event::Button {
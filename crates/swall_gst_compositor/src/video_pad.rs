@@ -0,0 +1,131 @@
+use gstreamer::glib;
+use gstreamer::glib::subclass::types::ObjectSubclassIsExt;
+use gstreamer::prelude::GstObjectExt;
+
+mod imp {
+    use std::sync::Mutex;
+
+    use gstreamer::glib::{
+        self,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+        value::ToValue,
+        ParamSpecBuilderExt,
+    };
+    use gstreamer::param_spec::GstParamSpecBuilderExt;
+    use gstreamer::subclass::prelude::{GstObjectImpl, PadImpl};
+    use gstreamer::{ClockTime, FormattedSegment, SingleShotClockId};
+    use once_cell::sync::Lazy;
+    use swall_compositor::config::Rect;
+
+    #[derive(Debug, Default)]
+    pub struct Settings {
+        /// Where the stream is drawn. A width or height of 0 uses the width or height of the video.
+        pub rect: Rect,
+        /// Size of the video from the negotiated caps
+        pub size: Option<(u32, u32)>,
+        /// Segment of the incoming buffers, for waiting until a frame is due
+        pub segment: FormattedSegment<ClockTime>,
+        /// What a frame is waiting on until it's due, so a flush can cut the wait short
+        pub clock_id: Option<SingleShotClockId>,
+        /// Between a flush starting and stopping. Frames are turned away.
+        pub flushing: bool,
+    }
+
+    /// Sink pad feeding a single video stream into the compositor
+    #[derive(Debug, Default)]
+    pub struct VideoSinkPad {
+        pub(crate) settings: Mutex<Settings>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for VideoSinkPad {
+        const NAME: &'static str = "sWallCompositorVideoSinkPad";
+        type Type = super::VideoSinkPad;
+        type ParentType = gstreamer::Pad;
+    }
+
+    impl GstObjectImpl for VideoSinkPad {}
+    impl PadImpl for VideoSinkPad {}
+    impl ObjectImpl for VideoSinkPad {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+                let coordinate = |name: &str, nick: &str, blurb: &str| {
+                    glib::ParamSpecUInt::builder(name)
+                        .nick(nick)
+                        .blurb(blurb)
+                        .mutable_playing()
+                        .build()
+                };
+                vec![
+                    coordinate("x", "X", "Left edge of the video on the canvas"),
+                    coordinate("y", "Y", "Top edge of the video on the canvas"),
+                    coordinate(
+                        "width",
+                        "Width",
+                        "Width of the video on the canvas, 0 for the width of the video",
+                    ),
+                    coordinate(
+                        "height",
+                        "Height",
+                        "Height of the video on the canvas, 0 for the height of the video",
+                    ),
+                ]
+            });
+
+            &PROPERTIES
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            let value = value
+                .get::<u32>()
+                .unwrap_or_else(|err| unreachable!("type checked upstream: {}", err));
+            let rect = &mut self.settings.lock().unwrap().rect;
+            match pspec.name() {
+                "x" => rect.x = value,
+                "y" => rect.y = value,
+                "width" => rect.width = value,
+                "height" => rect.height = value,
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            let rect = self.settings.lock().unwrap().rect;
+            match pspec.name() {
+                "x" => rect.x.to_value(),
+                "y" => rect.y.to_value(),
+                "width" => rect.width.to_value(),
+                "height" => rect.height.to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct VideoSinkPad(ObjectSubclass<imp::VideoSinkPad>) @extends gstreamer::Pad, gstreamer::Object;
+}
+
+impl VideoSinkPad {
+    /// Template name of the pads. Pads are named after it with a number the stream is known by.
+    pub(crate) const TEMPLATE_NAME: &'static str = "sink_%u";
+
+    /// The stream number from the pad name
+    pub(crate) fn id(&self) -> u32 {
+        parse_id(&self.name()).expect("Video sink pads are always named 'sink_<number>'")
+    }
+
+    /// Turn frames away until the flush stops and wake up a frame that is waiting to be due
+    pub(crate) fn start_flush(&self) {
+        let mut settings = self.imp().settings.lock().unwrap();
+        settings.flushing = true;
+        if let Some(clock_id) = settings.clock_id.take() {
+            clock_id.unschedule();
+        }
+    }
+}
+
+/// The stream number of a pad named `sink_<number>`
+pub(crate) fn parse_id(name: &str) -> Option<u32> {
+    name.strip_prefix("sink_")?.parse().ok()
+}